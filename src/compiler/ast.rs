@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use super::token::TokenType;
 
 #[derive(Debug, Clone)]
@@ -6,28 +5,78 @@ pub enum Expression {
     // Prefix
     IntegerExpression(String),
     FloatExpression(String),
+    StringExpression(String),
+    BooleanExpression(bool),
+    ArrayExpression(i32, Vec<Box<Expression>>), // {1, 2, 3}
+    VariableExpression(i32, String),
+    StructExpression(i32, String), // The struct itself, as the receiver of a call
     PrefixExpression(i32, TokenType, Box<Expression>),
+    InstanceExpression {
+        struct_name: String,
+        members: Vec<(String, Box<Expression>)>,
+        line: i32,
+    },
+    MessageSend {
+        // None for the shorthand without a receiver ([isScript])
+        receiver: Option<Box<Expression>>,
+        args_or_name: Result<Vec<(String, Box<Expression>)>, String>,
+        line: i32,
+    },
 
     // Infix and Postfix
     BinaryExpression(i32, TokenType, Box<Expression>, Box<Expression>),
+    MemberExpression(i32, Box<Expression>, String), // this.data
 
     // Other
-    ModuleDeclaration(String),
+    // None exports every struct, Some only the listed ones
+    ModuleDeclaration(i32, String, Option<Vec<String>>),
+    ImportDeclaration {
+        module: String,
+        // None imports every struct of the module (import), Some only the listed ones (use)
+        names: Option<Vec<String>>,
+        line: i32,
+    },
     StructDeclaration {
         name: String,
-        // Storing members...
-        // Storing parent...
-        // Storing composers...
+        members: Vec<(String, String)>,
+        parent: Option<String>, // struct Main [Nop]
+        composers: Vec<String>, // struct Main <Nop, Other>
+        line: i32,
     },
     MessageDeclaration {
         bound_struct: String,
-        args_or_name: Result<HashMap<String, String>, String>,
-        // Store body...
+        is_call: bool, // Sent to the struct itself (call) instead of its instances (message)
+        type_params: Vec<String>, // Generic type names: message(T) Box [wrap: T] -> T
+        args_or_name: Result<Vec<(String, String)>, String>, // Keywords and their types, in declaration order
+        selector: String, // Canonical name: the message name, or its keywords (x:y:)
+        body: Vec<Box<Expression>>,
         ret_value: Option<String>,
+        line: i32,
+    },
+    MainDeclaration {
+        body: Vec<Box<Expression>>, // Run when the module is run
+        line: i32,
     },
     LetStatement {
+        id: usize, // Unique in its file, for tables of information about the binding (like its type)
         bound_name: String,
         ntype: Option<String>,
-        expression: Box<Expression>
+        expression: Box<Expression>,
+        line: i32,
     },
+    ReturnStatement(i32, Box<Expression>),
+    IfStatement {
+        condition: Box<Expression>,
+        body: Vec<Box<Expression>>,
+        else_body: Option<Vec<Box<Expression>>>,
+        line: i32,
+    },
+}
+
+// The canonical name of a message: its name, or its keywords in order (at:put:)
+pub fn selector_name<T>(args_or_name: &Result<Vec<(String, T)>, String>) -> String {
+    match *args_or_name {
+        Ok(ref args) => args.iter().map(|a| a.0.clone() + ":").collect(),
+        Err(ref name) => name.clone()
+    }
 }
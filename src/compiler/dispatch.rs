@@ -0,0 +1,239 @@
+// Message dispatch: finds the declaration a message send runs, through the struct hierarchy
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::rc::Rc;
+use super::ast::Expression;
+use super::module::{Module, ModuleManager};
+use super::resolver::{Resolver, ResolveErrorKind};
+use super::typecheck::{Type, structured_type};
+
+// Where a declaration is: the module, and its index in the module code
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dispatch {
+    pub module: String,
+    pub index: usize,
+    pub owner: Type, // The struct in the hierarchy of the receiver that declares the message
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DispatchError {
+    DoesNotUnderstand(Type, String), // No message (1) anywhere in the hierarchy of (0)
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            DispatchError::DoesNotUnderstand(ref t, ref sel) => write!(fmt, "{} does not understand [{}]", t, sel),
+        }
+    }
+}
+
+// A struct declaration, with its parent and composers resolved in the module that declares it
+#[derive(Clone, Debug)]
+struct StructEntry {
+    module: String,
+    index: usize,
+    parent: Option<Type>,
+    composers: Vec<Type>,
+}
+
+// The structs and messages of the modules. It stays right until the modules change, so dispatchers
+// over the same modules can share it instead of indexing them again.
+#[derive(Default)]
+pub struct DispatchTable {
+    structs: HashMap<Type, StructEntry>,
+    methods: HashMap<(Type, bool, String), (String, usize)>, // (bound struct, is call, selector)
+}
+
+pub struct Dispatcher<'a> {
+    mman: &'a ModuleManager,
+    resolver: Resolver<'a>,
+    table: Rc<DispatchTable>,
+}
+
+impl<'a> Dispatcher<'a> {
+    // Indexes the structs and messages of every module. Names that do not resolve are left out,
+    // the resolver reports those.
+    pub fn new(mman: &'a ModuleManager) -> Dispatcher<'a> {
+        let mut dispatcher = Dispatcher::with_table(mman, Rc::new(DispatchTable::default()));
+        let mut table = DispatchTable::default();
+        for m in mman.find_modules_under("") {
+            for (index, inst) in m.get_code().iter().enumerate() {
+                match **inst {
+                    Expression::StructDeclaration { ref name, ref parent, ref composers, line, .. } => {
+                        // A struct of Std named like a builtin type declares that type (struct Integer [Number] in Std.Number).
+                        // Other modules cannot, the resolver reports those.
+                        let t = match Type::builtin(name) {
+                            Some(t) if m.is_std() => t,
+                            Some(_) => continue,
+                            None => Type::Instance(m.get_full_name(), name.clone())
+                        };
+                        let entry = StructEntry {
+                            module: m.get_full_name(),
+                            index,
+                            parent: parent.as_ref().and_then(|p| dispatcher.named_type(&m, p, line).ok()),
+                            composers: composers.iter().filter_map(|c| dispatcher.named_type(&m, c, line).ok()).collect(),
+                        };
+                        table.structs.insert(t, entry);
+                    },
+                    Expression::MessageDeclaration { ref bound_struct, is_call, ref type_params, ref selector, line, .. } => {
+                        if let Ok(t) = dispatcher.declared_type(&m, bound_struct, type_params, line) {
+                            let t = if t.has_variables() { t.generic() } else { t };
+                            // Modules are in name order, and the first declaration wins
+                            table.methods.entry((t, is_call, selector.clone())).or_insert((m.get_full_name(), index));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+        dispatcher.table = Rc::new(table);
+        dispatcher
+    }
+
+    // A dispatcher over modules that another one indexed, and that did not change since
+    pub fn with_table(mman: &'a ModuleManager, table: Rc<DispatchTable>) -> Dispatcher<'a> {
+        Dispatcher { mman, resolver: Resolver::new(mman), table }
+    }
+
+    pub fn table(&self) -> Rc<DispatchTable> {
+        self.table.clone()
+    }
+
+    // Resolves a type name as seen from the module m
+    pub fn named_type(&self, m: &Module, name: &str, line: i32) -> Result<Type, ResolveErrorKind> {
+        self.declared_type(m, name, &[], line)
+    }
+
+    // Resolves a type name of a message declaration, where the generic type names stand for themselves
+    pub fn declared_type(&self, m: &Module, name: &str, type_params: &[String], line: i32) -> Result<Type, ResolveErrorKind> {
+        structured_type(name, &mut |name| {
+            if type_params.iter().any(|p| p == name) {
+                return Ok(Type::Variable(name.to_string()))
+            }
+            if let Some(t) = Type::builtin(name) {
+                return Ok(t)
+            }
+            match self.resolver.resolve_struct(m, name, line) {
+                Ok(module) => Ok(Type::Instance(module, name.to_string())),
+                Err(e) => Err(e.kind)
+            }
+        })
+    }
+
+    // The order in which a struct and its ancestors are searched: the struct, then what it is composed of,
+    // then its parent chain. Each struct is searched once, even if the hierarchy loops.
+    // Collections are searched for their element type, then for any element type.
+    pub fn hierarchy(&self, t: &Type) -> Vec<Type> {
+        let mut order = vec![];
+        self.walk(t, &mut order);
+        order
+    }
+
+    fn walk(&self, t: &Type, order: &mut Vec<Type>) {
+        if order.contains(t) {
+            return
+        }
+        order.push(t.clone());
+        if let Some(entry) = self.table.structs.get(t) {
+            for c in entry.composers.iter() {
+                self.walk(c, order);
+            }
+            if let Some(ref p) = entry.parent {
+                self.walk(p, order);
+            }
+        }
+        let generic = t.generic();
+        if generic != *t {
+            self.walk(&generic, order);
+        }
+    }
+
+    // True if a value of type t can be used where `of` is expected: the same type, or a parent of it.
+    // Collections never change, so a collection of a type fits where a collection of its parent is expected.
+    pub fn is_subtype(&self, t: &Type, of: &Type) -> bool {
+        if t == of {
+            return true
+        }
+        match (t, of) {
+            (Type::Class(t), Type::Class(of)) => self.is_subtype(t, of),
+            (Type::Array(t), Type::Array(of)) | (Type::Set(t), Type::Set(of)) => self.is_element_subtype(t, of),
+            (Type::Map(tk, tv), Type::Map(ok, ov)) => self.is_element_subtype(tk, ok) && self.is_element_subtype(tv, ov),
+            _ => {
+                let mut current = self.table.structs.get(t).and_then(|e| e.parent.clone());
+                let mut seen = vec![t.clone()];
+                while let Some(p) = current {
+                    if p == *of {
+                        return true
+                    }
+                    if seen.contains(&p) {
+                        break
+                    }
+                    current = self.table.structs.get(&p).and_then(|e| e.parent.clone());
+                    seen.push(p);
+                }
+                false
+            }
+        }
+    }
+
+    // The elements of an empty collection are Nothing, and fit anywhere
+    fn is_element_subtype(&self, t: &Type, of: &Type) -> bool {
+        *t == Type::Nothing || *of == Type::any() || self.is_subtype(t, of)
+    }
+
+    // The receiver of a shorthand send where there is no this (in main or a script): [isScript] is
+    // [System isScript] when Std.System has the call
+    pub fn implicit_receiver(&self, selector: &str) -> Option<Type> {
+        let system = Type::Class(Box::new(Type::Instance("Std.System".to_string(), "System".to_string())));
+        self.resolve(&system, selector).ok().map(|_| system)
+    }
+
+    // Finds the message (or call, for a Class receiver) that a send of the selector runs
+    pub fn resolve(&self, receiver: &Type, selector: &str) -> Result<Dispatch, DispatchError> {
+        let (is_call, target) = match *receiver {
+            Type::Class(ref t) => (true, (**t).clone()),
+            ref t => (false, t.clone())
+        };
+        for owner in self.hierarchy(&target) {
+            if let Some(&(ref module, index)) = self.table.methods.get(&(owner.clone(), is_call, selector.to_string())) {
+                return Ok(Dispatch { module: module.clone(), index, owner })
+            }
+        }
+        Err(DispatchError::DoesNotUnderstand(receiver.clone(), selector.to_string()))
+    }
+
+    // Every member of a struct instance, with those it gets from its composers and parents,
+    // and their types resolved in the module that declares them. None for builtin types, which have no instances.
+    pub fn members(&self, t: &Type) -> Option<Vec<(String, Result<Type, ResolveErrorKind>)>> {
+        if !matches!(*t, Type::Instance(..)) {
+            return None
+        }
+        self.table.structs.get(t)?;
+        let mut members: Vec<(String, Result<Type, ResolveErrorKind>)> = vec![];
+        for owner in self.hierarchy(t) {
+            if let Some((m, decl)) = self.struct_declaration(&owner) {
+                if let Expression::StructDeclaration { members: ref declared, line, .. } = *decl {
+                    for mb in declared.iter() {
+                        if !members.iter().any(|e| e.0 == mb.0) {
+                            members.push((mb.0.clone(), self.named_type(m, &mb.1, line)));
+                        }
+                    }
+                }
+            }
+        }
+        Some(members)
+    }
+
+    pub fn struct_declaration(&self, t: &Type) -> Option<(&'a Module, &'a Expression)> {
+        let entry = self.table.structs.get(t)?;
+        let m = self.mman.get_module(&entry.module)?;
+        Some((m, &m.get_code()[entry.index]))
+    }
+
+    // The module and declaration a dispatch found
+    pub fn declaration(&self, d: &Dispatch) -> Option<(&'a Module, &'a Expression)> {
+        let m = self.mman.get_module(&d.module)?;
+        Some((m, &m.get_code()[d.index]))
+    }
+}
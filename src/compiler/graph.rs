@@ -0,0 +1,125 @@
+// The import graph between modules, used to order modules for the later compilation stages
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use super::module::ModuleManager;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CycleError {
+    pub cycles: Vec<Vec<String>>, // Each cycle starts and ends with the same module
+}
+
+impl Display for CycleError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        for (i, cycle) in self.cycles.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            write!(fmt, "Import cycle: {}", cycle.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DependencyGraph {
+    // Module name to the names of the modules it imports. Imports of unknown modules are left out,
+    // the resolver reports those.
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mark {
+    Visiting,
+    Done,
+}
+
+impl DependencyGraph {
+    pub fn new(mman: &ModuleManager) -> DependencyGraph {
+        let mut edges = BTreeMap::new();
+        for m in mman.find_modules_under("") {
+            let deps = m.get_imports().into_iter()
+                .map(|(module, _, _)| module)
+                .filter(|module| mman.find_module(module).is_some())
+                .collect::<BTreeSet<String>>();
+            edges.insert(m.get_full_name(), deps);
+        }
+        DependencyGraph { edges }
+    }
+
+    pub fn get_dependencies(&self, module: &str) -> Vec<String> {
+        match self.edges.get(module) {
+            Some(deps) => deps.iter().cloned().collect(),
+            None => vec![]
+        }
+    }
+
+    // Every distinct import cycle, as the path that leads back to its first module (A -> B -> A)
+    pub fn find_cycles(&self) -> Vec<Vec<String>> {
+        let mut marks = BTreeMap::new();
+        let mut cycles = vec![];
+        for module in self.edges.keys() {
+            let mut path = vec![];
+            self.visit(module, &mut marks, &mut path, &mut |p| {
+                // Rotate the cycle to start at its smallest name, so each cycle is only reported once
+                let start = (0..p.len()).min_by_key(|&i| &p[i]).unwrap();
+                let mut cycle = p[start..].to_vec();
+                cycle.extend(p[..start].iter().cloned());
+                cycle.push(cycle[0].clone());
+                if !cycles.contains(&cycle) {
+                    cycles.push(cycle);
+                }
+            }, &mut |_| {});
+        }
+        cycles
+    }
+
+    // All modules, with every module after the modules it imports
+    pub fn topological_order(&self) -> Result<Vec<String>, CycleError> {
+        let cycles = self.find_cycles();
+        if !cycles.is_empty() {
+            return Err(CycleError { cycles })
+        }
+        let mut marks = BTreeMap::new();
+        let mut order = vec![];
+        for module in self.edges.keys() {
+            let mut path = vec![];
+            self.visit(module, &mut marks, &mut path, &mut |_| {}, &mut |m| order.push(m.to_string()));
+        }
+        Ok(order)
+    }
+
+    // Depth first walk over the imports. on_cycle gets the part of the path that loops back,
+    // on_done gets every module once all of its imports are done.
+    fn visit(&self, module: &str, marks: &mut BTreeMap<String, Mark>, path: &mut Vec<String>,
+             on_cycle: &mut dyn FnMut(&[String]), on_done: &mut dyn FnMut(&str)) {
+        match marks.get(module).cloned() {
+            Some(Mark::Done) => return,
+            Some(Mark::Visiting) => {
+                let start = path.iter().position(|m| m == module).unwrap();
+                on_cycle(&path[start..]);
+                return
+            },
+            None => {}
+        }
+        marks.insert(module.to_string(), Mark::Visiting);
+        path.push(module.to_string());
+        for dep in self.get_dependencies(module) {
+            self.visit(&dep, marks, path, on_cycle, on_done);
+        }
+        path.pop();
+        marks.insert(module.to_string(), Mark::Done);
+        on_done(module);
+    }
+
+    // The graph in Graphviz DOT format, with an edge from each module to the modules it imports
+    pub fn to_dot(&self) -> String {
+        let mut s = "digraph modules {\n".to_string();
+        for (module, deps) in self.edges.iter() {
+            s = s + &format!("    \"{}\";\n", module);
+            for dep in deps.iter() {
+                s = s + &format!("    \"{}\" -> \"{}\";\n", module, dep);
+            }
+        }
+        s + "}\n"
+    }
+}
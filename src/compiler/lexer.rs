@@ -1,20 +1,166 @@
+use std::cell::OnceCell;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::BufRead;
 use std::iter::Iterator;
+use std::rc::Rc;
 use super::token::{Token, TokenType, TokenStream};
 use super::trie::{Trie, TrieError};
 use std::collections::HashMap;
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyntaxErrorKind {
+    Unreadable(String), // The source could not be read, for the reason (0)
+    UnknownSymbol(char),
+    InvalidOperator(String), // Operator characters that do not make an operator
+    DuplicateOperator(String),
+    UnterminatedString(String),
+    Unexpected(TokenType, TokenType), // (0) was expected, but (1) was found
+    CouldNotParse(String),
+    ScriptDeclaration(String), // A script cannot declare a module or a main block
+    DuplicateKeyword(String, String), // Keyword (0) is used twice in a message of (1)
+    UnclosedBlock,
+    NotInfix(String), // operator (0), which is not infix
+    NotAnOperator(String),
+    InvalidPrecedence(String), // The precedence of operator (0) is not a positive integer
+    InvalidAssociativity(String),
+    ExpectedSelector(String),
+    NoModuleDeclaration,
+    ModuleRedeclared(String, String), // A module declared as (0), then as (1)
+    UndeclaredExport(String, String), // Module (0) exports (1), but does not declare it
+    NameTaken(String), // Another module is already loaded as (0)
+    NotAScript, // The path of a script has no file name to name it after
+}
+
+// An error of the lexer or the parser, or of the declarations that make a file a module
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+    pub file: String,
+    pub line: i32,
+    pub kind: SyntaxErrorKind,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}:{}: ", self.file, self.line)?;
+        match self.kind {
+            SyntaxErrorKind::Unreadable(ref e) => write!(fmt, "Cannot read the source: {}", e),
+            SyntaxErrorKind::UnknownSymbol(c) => write!(fmt, "Unknown symbol {}", c),
+            SyntaxErrorKind::InvalidOperator(ref op) => write!(fmt, "Error lexing {}", op),
+            SyntaxErrorKind::DuplicateOperator(ref op) => write!(fmt, "Operator {} is already defined", op),
+            SyntaxErrorKind::UnterminatedString(ref s) => write!(fmt, "Unterminated string \"{}", s),
+            SyntaxErrorKind::Unexpected(expected, found) =>
+                write!(fmt, "Token type mismatch: {:?} expected, {:?} received", expected, found),
+            SyntaxErrorKind::CouldNotParse(ref text) => write!(fmt, "Could not parse '{}'", text),
+            SyntaxErrorKind::ScriptDeclaration(ref what) => write!(fmt, "Scripts cannot declare '{}'", what),
+            SyntaxErrorKind::DuplicateKeyword(ref k, ref s) => write!(fmt, "Duplicate keyword {} in message {} [...]", k, s),
+            SyntaxErrorKind::UnclosedBlock => write!(fmt, "Unclosed block at end of file"),
+            SyntaxErrorKind::NotInfix(ref fixity) => write!(fmt, "Only infix operators can be declared, not {}", fixity),
+            SyntaxErrorKind::NotAnOperator(ref text) => write!(fmt, "{} cannot be declared as an operator", text),
+            SyntaxErrorKind::InvalidPrecedence(ref op) => write!(fmt, "The precedence of {} must be a positive integer", op),
+            SyntaxErrorKind::InvalidAssociativity(ref a) => write!(fmt, "Operators are left or right associative, not {}", a),
+            SyntaxErrorKind::ExpectedSelector(ref text) => write!(fmt, "Expected selector, found {}", text),
+            SyntaxErrorKind::NoModuleDeclaration => write!(fmt, "Must declare module name"),
+            SyntaxErrorKind::ModuleRedeclared(ref a, ref b) => write!(fmt, "Cannot declare module as {} and {}", a, b),
+            SyntaxErrorKind::UndeclaredExport(ref m, ref e) => write!(fmt, "Module {} exports {}, but does not declare it", m, e),
+            SyntaxErrorKind::NameTaken(ref name) => write!(fmt, "Name already taken: {}", name),
+            SyntaxErrorKind::NotAScript => write!(fmt, "Not a script file"),
+        }
+    }
+}
+
+// The words and symbols of a dialect. The trie of its operators is built the first time it lexes,
+// and shared by every lexer that uses the config after that.
+#[derive(Clone, Default)]
+pub struct LexerConfig {
+    keywords: HashMap<String, TokenType>,
+    accept: Vec<char>, // The characters of operators, sorted
+    operators: Vec<(String, TokenType)>,
+    trie: OnceCell<Trie<'static, TokenType>>,
+}
+
+impl LexerConfig {
+    // No keywords or operators: every word is an identifier
+    pub fn new() -> LexerConfig {
+        LexerConfig::default()
+    }
+
+    // The Kobold language
+    pub fn kobold() -> LexerConfig {
+        let keywords = [("module", TokenType::Module), ("struct", TokenType::Struct), ("let", TokenType::Let),
+            ("if", TokenType::If), ("inner", TokenType::Inner), ("message", TokenType::Message), ("call", TokenType::Call),
+            ("import", TokenType::Import), ("use", TokenType::Use), ("return", TokenType::Return), ("then", TokenType::Then),
+            ("else", TokenType::Else), ("true", TokenType::True), ("false", TokenType::False), ("main", TokenType::Main)];
+        let operators = [("{", TokenType::LBrace), ("}", TokenType::RBrace), ("->", TokenType::Arrow), ("-", TokenType::Minus),
+            ("[", TokenType::LBracket), ("]", TokenType::RBracket), ("<", TokenType::LessThan), (">", TokenType::GreaterThan),
+            ("=", TokenType::Equal), ("==", TokenType::DoubleEqual), ("+", TokenType::Plus), ("*", TokenType::Asterisk),
+            ("**", TokenType::Power), ("/", TokenType::Backslash), (",", TokenType::Comma), (":", TokenType::Colon),
+            ("(", TokenType::LParen), (")", TokenType::RParen), (".", TokenType::Period)];
+        let config = keywords.iter().fold(LexerConfig::new(), |config, &(word, tt)| config.with_keyword(word, tt));
+        operators.iter().fold(config, |config, &(symbol, tt)| config.with_operator(symbol, tt))
+            .with_operator_chars(&['|', '&', '%', '^', '~', '@', '$', '!', '?']) // For the operators that modules declare
+    }
+
+    pub fn with_keyword(mut self, word: &str, tt: TokenType) -> LexerConfig {
+        self.keywords.insert(word.to_string(), tt);
+        self
+    }
+
+    pub fn with_operator(mut self, symbol: &str, tt: TokenType) -> LexerConfig {
+        let chars = symbol.chars().collect::<Vec<_>>();
+        self.operators.push((symbol.to_string(), tt));
+        self.with_operator_chars(&chars)
+    }
+
+    // Characters that can be used in operators without being one yet
+    pub fn with_operator_chars(mut self, chars: &[char]) -> LexerConfig {
+        self.accept.extend(chars);
+        self.accept.sort();
+        self.accept.dedup();
+        self.trie = OnceCell::new();
+        self
+    }
+
+    fn accepts(&self, c: char) -> bool {
+        self.accept.binary_search(&c).is_ok()
+    }
+
+    fn trie(&self) -> &Trie<'static, TokenType> {
+        self.trie.get_or_init(|| {
+            let accept = self.accept.clone();
+            let mut t = Trie::new(accept.len(), move |c| match accept.binary_search(&c) {
+                Ok(ind) => ind as i32,
+                Err(_) => -1
+            });
+            for &(ref symbol, tt) in &self.operators {
+                t.add_string(symbol, tt);
+            }
+            t
+        })
+    }
+}
+
+thread_local! {
+    static KOBOLD: Rc<LexerConfig> = Rc::new(LexerConfig::kobold());
+}
+
+// Lexes a source as it reads it, one line at a time. The lexer is an iterator of the tokens,
+// which end with EndOfFile, or with the first error.
 pub struct Lexer<T: BufRead> {
     source: T,
     source_name: String,
-
-    keywords: HashMap<String, TokenType>,
-    accept_vec: Vec<char>,
+    config: Rc<LexerConfig>,
+    declared: Option<Trie<'static, TokenType>>, // The trie of the config with the operators that the file declares
+    user_operators: usize,
 
     // Mutable state
     line: i32,
-    ts: TokenStream,
-    ht: bool
+    text: Vec<char>, // The line being lexed
+    at: usize,
+    last_line: i32, // The line of the last token
+    after_operator: bool, // The last token was operator, at the start of a line
+    declaring: bool, // The last tokens were operator infix
+    ended: bool, // EndOfFile was given
+    failed: bool, // An error was given, and ends the tokens
 }
 
 #[derive(Debug)]
@@ -28,188 +174,274 @@ enum LexerState {
     Float,
     CString,
     Operator,
+    OperatorDeclaration, // The symbol of operator infix <+> ...
 
     OneLineComment,
 }
 
 impl<T: BufRead> Lexer<T> {
     pub fn new(name: &str, r: T) -> Lexer<T> {
-        let mut tmp = Lexer {
+        Lexer {
             source_name: name.to_string(),
             source: r,
+            config: KOBOLD.with(|config| config.clone()),
+            declared: None,
+            user_operators: 0,
             line: 1,
-            ts: TokenStream::new(),
-            ht: false,
-            keywords: HashMap::new(),
-            accept_vec: vec!['{', '}', '-', '>', '[', ']', '<', '=', '+', '*', '/', ',', ':', '(', ')', '.'],
-        };
+            text: vec![],
+            at: 0,
+            last_line: 0,
+            after_operator: false,
+            declaring: false,
+            ended: false,
+            failed: false,
+        }
+    }
 
-        tmp.keywords.insert("module".to_string(), TokenType::Module);
-        tmp.keywords.insert("struct".to_string(), TokenType::Struct);
-        tmp.keywords.insert("let".to_string(), TokenType::Let);
-        tmp.keywords.insert("if".to_string(), TokenType::If);
-        tmp.keywords.insert("inner".to_string(), TokenType::Inner);
-        tmp.keywords.insert("message".to_string(), TokenType::Message);
+    // Lexes another dialect than Kobold
+    pub fn with_config(mut self, config: Rc<LexerConfig>) -> Lexer<T> {
+        self.config = config;
+        self
+    }
+
+    // Lexes the rest of the source
+    pub fn process(&mut self) -> Result<TokenStream, SyntaxError> {
+        let mut ts = TokenStream::new();
+        for t in self {
+            ts.add(t?);
+        }
+        Ok(ts)
+    }
+
+    fn error(&self, kind: SyntaxErrorKind) -> SyntaxError {
+        SyntaxError { file: self.source_name.clone(), line: self.line, kind }
+    }
+
+    fn trie(&self) -> &Trie<'static, TokenType> {
+        match self.declared {
+            Some(ref t) => t,
+            None => self.config.trie()
+        }
+    }
+
+    // The next character, reading the next line when the current one is done. None at the end of the source.
+    fn next_char(&mut self) -> Result<Option<char>, SyntaxError> {
+        let c = self.peek_char()?;
+        if c.is_some() {
+            self.at += 1;
+        }
+        Ok(c)
+    }
+
+    fn peek_char(&mut self) -> Result<Option<char>, SyntaxError> {
+        if self.at == self.text.len() {
+            let mut line = String::new();
+            match self.source.read_line(&mut line) {
+                Ok(0) => return Ok(None), // The last line is kept, to give back characters from
+                Ok(_) => {},
+                Err(e) => return Err(self.error(SyntaxErrorKind::Unreadable(e.to_string())))
+            }
+            self.text = line.chars().collect();
+            self.at = 0;
+        }
+        Ok(self.text.get(self.at).cloned())
+    }
+
+    // Gives back the last character read. The characters of a token are always on the current line,
+    // so this can be done for all of them.
+    fn unread(&mut self) {
+        self.at -= 1;
+    }
 
-        tmp.accept_vec.sort();
+    // An identifier, or a keyword of the config
+    fn word(&mut self, data: &str) -> Token {
+        let tt = self.config.keywords.get(data).cloned().unwrap_or(TokenType::Identifier);
+        self.token(tt, data)
+    }
 
-        tmp
+    // The symbol of operator infix, which is an operator for the rest of the source
+    fn declare(&mut self, data: &str) -> Result<Token, SyntaxError> {
+        if self.trie().search(data).is_ok() {
+            return Err(self.error(SyntaxErrorKind::DuplicateOperator(data.to_string())))
+        }
+        let tt = TokenType::UserOperator(self.user_operators);
+        self.user_operators += 1;
+        let config = self.config.clone();
+        self.declared.get_or_insert_with(|| config.trie().clone()).add_string(data, tt);
+        Ok(self.token(tt, data))
     }
 
-    pub fn process(&mut self) -> TokenStream {
-        let avsnp = self.accept_vec.clone();
-        let space_idx = |a| match avsnp.binary_search(&a) {
-            Ok(ind) => ind as i32,
-            Err(_) => -1
+    // The end of the source ends the token being lexed. After it comes EndOfFile, once.
+    fn finish(&mut self, state: LexerState, data: &str) -> Result<Option<Token>, SyntaxError> {
+        let token = match state {
+            LexerState::Identifier => self.word(data),
+            LexerState::StructIdentifier => self.token(TokenType::StructIdentifier, data),
+            LexerState::Integer => self.token(TokenType::Integer, data),
+            LexerState::Float => self.token(TokenType::Float, data),
+            LexerState::Operator => match self.trie().search(data) {
+                Ok(tt) => self.token(tt, data),
+                Err(_) => return Err(self.error(SyntaxErrorKind::InvalidOperator(data.to_string())))
+            },
+            LexerState::OperatorDeclaration => self.declare(data)?,
+            LexerState::CString => return Err(self.error(SyntaxErrorKind::UnterminatedString(data.to_string()))),
+            LexerState::Default | LexerState::NewlineRN | LexerState::OneLineComment if !self.ended => {
+                self.ended = true;
+                self.token(TokenType::EndOfFile, "")
+            },
+            LexerState::Default | LexerState::NewlineRN | LexerState::OneLineComment => return Ok(None)
         };
-        let mut t = Trie::new(self.accept_vec.len(), &space_idx);
-        t.add_string("{", TokenType::LBrace);
-        t.add_string("}", TokenType::RBrace);
-        t.add_string("->", TokenType::Arrow);
-        t.add_string("-", TokenType::Minus);
-        t.add_string("[", TokenType::LBracket);
-        t.add_string("]", TokenType::RBracket);
-        t.add_string("<", TokenType::LessThan);
-        t.add_string(">", TokenType::GreaterThan);
-        t.add_string("=", TokenType::Equal);
-        t.add_string("+", TokenType::Plus);
-        t.add_string("*", TokenType::Asterisk);
-        t.add_string("**", TokenType::Power);
-        t.add_string("/", TokenType::Backslash);
-        t.add_string(",", TokenType::Comma);
-        t.add_string(":", TokenType::Colon);
-        t.add_string("(", TokenType::LParen);
-        t.add_string(")", TokenType::RParen);
-        t.add_string(".", TokenType::Period);
-        let ref mut ts = self.ts;
-        if !self.ht {
-            let mut state = LexerState::Default;
-            let mut data = "".to_string();
-            let mut file = "".to_string(); self.source.read_to_string(&mut file).ok();
-            // println!("{:#?}", file);
-            let mut fc = file.chars();
-            let mut c = ' ';
-            let mut advance = true;
-            while fc.clone().count() > 0 {
-                match state {
-                    LexerState::Default => {
-                        if advance {
-                            c = match fc.next(){Some(h)=>h,_=>break};
-                        } else { advance = true; }
-                        data = "".to_string();
-                        match c {
-                            'a'...'z' => {data.push(c); state = LexerState::Identifier;},
-                            'A'...'Z' => {data.push(c); state = LexerState::StructIdentifier;},
-                            '0'...'9' => {data.push(c); state = LexerState::Integer;},
-                            '#' => state = LexerState::OneLineComment,
-                            '\r' => state = LexerState::NewlineRN,
-                            '\n' => self.line += 1,
-                            ';' => {},
-                            '"' => state = LexerState::CString,
-                            _ => {
-                                if !c.is_whitespace() {
-                                    if self.accept_vec.binary_search(&c).is_ok() {
-                                        state = LexerState::Operator;
-                                    } else {
-                                        panic!("{}:{}: Unknown symbol {}", self.source_name, self.line, c);
-                                    }
-                                }
-                            }
+        Ok(Some(token))
+    }
+
+    // operator and infix are identifiers, which declare an operator when a line starts with them
+    fn token(&mut self, tt: TokenType, text: &str) -> Token {
+        self.declaring = self.after_operator && tt == TokenType::Identifier && text == "infix";
+        self.after_operator = tt == TokenType::Identifier && text == "operator" && self.line != self.last_line;
+        self.last_line = self.line;
+        Token::new(tt, text).with_line(self.line)
+    }
+
+    // The next token, None after EndOfFile
+    fn lex(&mut self) -> Result<Option<Token>, SyntaxError> {
+        let mut state = LexerState::Default;
+        let mut data = String::new();
+        let mut found = None; // The longest operator that data starts, and its length
+        loop {
+            match state {
+                LexerState::Default => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    match c {
+                        'a'...'z' => {data.push(c); state = LexerState::Identifier;},
+                        'A'...'Z' => {data.push(c); state = LexerState::StructIdentifier;},
+                        '0'...'9' => {data.push(c); state = LexerState::Integer;},
+                        '#' => state = LexerState::OneLineComment,
+                        '\r' => state = LexerState::NewlineRN,
+                        '\n' => self.line += 1,
+                        ';' => {},
+                        '"' => state = LexerState::CString,
+                        _ if c.is_whitespace() => {},
+                        _ if self.config.accepts(c) => {
+                            data.push(c);
+                            state = if self.declaring { LexerState::OperatorDeclaration } else { LexerState::Operator };
+                        },
+                        _ => return Err(self.error(SyntaxErrorKind::UnknownSymbol(c)))
+                    }
+                },
+                LexerState::NewlineRN => {
+                    match match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) } {
+                        '\n' => self.line += 1,
+                        _ => self.unread()
+                    }
+                    state = LexerState::Default;
+                },
+                LexerState::Identifier => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    match c {
+                        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '!' | '?' => data.push(c),
+                        _ => {
+                            self.unread();
+                            return Ok(Some(self.word(&data)))
                         }
-                    },
-                    LexerState::NewlineRN => {
-                        c = match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            '\n' => {self.line += 1; state = LexerState::Default},
-                            _ => {advance = false; state = LexerState::Default},
+                    }
+                },
+                LexerState::StructIdentifier => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    match c {
+                        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => data.push(c),
+                        _ => {
+                            self.unread();
+                            return Ok(Some(self.token(TokenType::StructIdentifier, &data)))
                         }
-                    },
-                    LexerState::Identifier => {
-                        c = match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '!' | '?' => data.push(c),
-                            _ => {
-                                let ntok = match self.keywords.get(&data) {
-                                    Some(typ) => Token::new(*typ, &data),
-                                    None => Token::new(TokenType::Identifier, &data)
-                                }.with_line(self.line);
-                                ts.add(ntok);
-                                advance = false;
-                                state = LexerState::Default;
-                            }
+                    }
+                },
+                LexerState::OneLineComment => {
+                    match match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) } {
+                        '\r' => state = LexerState::NewlineRN,
+                        '\n' => {self.line += 1; state = LexerState::Default},
+                        _ => {},
+                    }
+                },
+                LexerState::Operator => {
+                    // The longest operator that data starts. After it, data can be the start of a longer one
+                    // (<+ of <+>), which is given back if the next character does not go on to it.
+                    if let Ok(tt) = self.trie().search(&data) {
+                        found = Some((tt, data.len()));
+                    }
+                    let longer = match self.peek_char()? {
+                        Some(c) => {
+                            let mut tmp = data.clone();
+                            tmp.push(c);
+                            matches!(self.trie().search(&tmp), Ok(_) | Err(TrieError::SubTrie))
+                        },
+                        None => false
+                    };
+                    if longer {
+                        if let Some(c) = self.next_char()? {
+                            data.push(c);
                         }
-                    },
-                    LexerState::StructIdentifier => {
-                        c = match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => data.push(c),
-                            _ => {
-                                ts.add(Token::new(TokenType::StructIdentifier, &data).with_line(self.line));
-                                advance = false;
-                                state = LexerState::Default;
+                        continue
+                    }
+                    return match found {
+                        Some((tt, len)) => {
+                            for _ in data[len..].chars() {
+                                self.unread();
                             }
-                        }
-                    },
-                    LexerState::OneLineComment => {
-                        c = match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            '\r' => state = LexerState::NewlineRN,
-                            '\n' => {self.line+=1; state = LexerState::Default},
-                            _ => {},
-                        }
-                    },
-                    LexerState::Operator => {
-                        // Assume that current c is accepted.
+                            data.truncate(len);
+                            Ok(Some(self.token(tt, &data)))
+                        },
+                        None => Err(self.error(SyntaxErrorKind::InvalidOperator(data)))
+                    }
+                },
+                LexerState::OperatorDeclaration => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    if self.config.accepts(c) {
                         data.push(c);
-                        match t.search(&data) {
-                            Ok(ty) => {
-                                let mut pk = fc.clone().peekable();
-                                if let Some(x) = pk.peek() {
-                                    let mut tmp = data.clone();
-                                    tmp.push(*x);
-                                    match t.search(&tmp) {
-                                        Err(TrieError::End)|Err(TrieError::Null)|Err(TrieError::NoHash)|Err(TrieError::NoChar) => { ts.add(Token::new(ty, &data).with_line(self.line)); state=LexerState::Default; },
-                                        _ => {c = match fc.next(){Some(h)=>h,_=>break};}
-                                    };
-                                } else {
-                                    ts.add(Token::new(ty, &data).with_line(self.line)); state=LexerState::Default;
-                                }
-                            },
-                            Err(TrieError::End)|Err(TrieError::Null)|Err(TrieError::NoHash)|Err(TrieError::NoChar) => {
-                                panic!("{}:{}: Error lexing {}", self.source_name, self.line, data);
-                            },
-                            _ => {c=match fc.next(){Some(h)=>h,_=>break};}
+                    } else {
+                        self.unread();
+                        return self.declare(&data).map(Some)
+                    }
+                },
+                LexerState::CString => {
+                    match match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) } {
+                        '"' => return Ok(Some(self.token(TokenType::CString, &data))),
+                        c => data.push(c)
+                    }
+                },
+                LexerState::Integer => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    match c {
+                        '0'...'9' => data.push(c),
+                        '.' | 'e' => {data.push(c); state = LexerState::Float},
+                        _ => {
+                            self.unread();
+                            return Ok(Some(self.token(TokenType::Integer, &data)))
                         }
-                    },
-                    LexerState::CString => {
-                        c=match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            '"' => {ts.add(Token::new(TokenType::CString, &data).with_line(self.line)); state=LexerState::Default},
-                            '/' => {
-                                // Escape sequence processing
-                            },
-                            _ => data.push(c)
-                        };
-                    },
-                    LexerState::Integer => {
-                        c=match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            '0'...'9' => data.push(c),
-                            '.' | 'e' => {data.push(c); state=LexerState::Float},
-                            _ => {ts.add(Token::new(TokenType::Integer, &data).with_line(self.line)); state=LexerState::Default;}
+                    }
+                },
+                LexerState::Float => {
+                    let c = match self.next_char()? { Some(c) => c, None => return self.finish(state, &data) };
+                    match c {
+                        '0'...'9' => data.push(c),
+                        _ => {
+                            self.unread();
+                            return Ok(Some(self.token(TokenType::Float, &data)))
                         }
-                    },
-                    LexerState::Float => {
-                        c=match fc.next(){Some(h)=>h,_=>break};
-                        match c {
-                            '0'...'9' => data.push(c),
-                            _ => {ts.add(Token::new(TokenType::Float, &data).with_line(self.line)); state=LexerState::Default;}
-                        }
-                    },
-                    // e @ _ => unreachable!("All states in a DST should be handled. {:?} {:?}", e, c)
-                };
+                    }
+                },
             }
         }
-        ts.clone()
+    }
+}
+
+impl<T: BufRead> Iterator for Lexer<T> {
+    type Item = Result<Token, SyntaxError>;
+
+    fn next(&mut self) -> Option<Result<Token, SyntaxError>> {
+        if self.failed {
+            return None
+        }
+        let next = self.lex();
+        self.failed = next.is_err();
+        next.transpose()
     }
 }
@@ -0,0 +1,555 @@
+// Precompiled libraries (.klb): a module saved after parsing, so it loads without lexing or parsing.
+//
+// All numbers are little endian, strings are a u32 byte length and UTF-8, lists are a u32 count and
+// their items, and optional values are a u8 (0 for none, 1 for some) and the value.
+//
+//     magic       "KLB\0"
+//     version     u16, FORMAT_VERSION. Libraries of any other version are rejected. Version 2 added array literals
+//                 and the script flag.
+//     module      string, the full module name
+//     source      string, the file the module was compiled from (line numbers refer to it)
+//     script      u8, 1 if the module was a script (.ksc)
+//     exports     optional list of strings
+//     structs     list of (name, optional parent, list of composers, list of (member, type))
+//     messages    list of (struct, is call u8, selector, list of argument types, optional return type, u32 code index)
+//     code        list of expressions, the module code without its module declaration
+//
+// The struct and message tables repeat what the code declares, for tools that list what a library
+// holds without decoding its code.
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{Read, Write};
+use super::ast::Expression;
+use super::module::Module;
+use super::token::TokenType;
+
+pub const MAGIC: &[u8; 4] = b"KLB\0";
+pub const FORMAT_VERSION: u16 = 2;
+
+// The operators that expressions can hold, by their number in the format
+const OPERATORS: [TokenType; 7] = [
+    TokenType::Plus, TokenType::Minus, TokenType::Asterisk, TokenType::Backslash,
+    TokenType::LessThan, TokenType::GreaterThan, TokenType::DoubleEqual,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LibraryErrorKind {
+    Io(String),
+    NotALibrary, // The file does not start with the magic bytes
+    IncompatibleVersion(u16),
+    Corrupt(String), // What could not be read
+    NameTaken(String), // Another module is already loaded with the name of the library
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryError {
+    pub file: String,
+    pub kind: LibraryErrorKind,
+}
+
+impl Display for LibraryError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}: ", self.file)?;
+        match self.kind {
+            LibraryErrorKind::Io(ref e) => write!(fmt, "Could not read library: {}", e),
+            LibraryErrorKind::NotALibrary => write!(fmt, "Not a Kobold library"),
+            LibraryErrorKind::IncompatibleVersion(v) =>
+                write!(fmt, "Library format version {} is not supported, expected version {}", v, FORMAT_VERSION),
+            LibraryErrorKind::Corrupt(ref what) => write!(fmt, "Corrupt library, could not read {}", what),
+            LibraryErrorKind::NameTaken(ref name) => write!(fmt, "Name already taken: {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructLayout {
+    pub name: String,
+    pub parent: Option<String>,
+    pub composers: Vec<String>,
+    pub members: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageEntry {
+    pub bound_struct: String,
+    pub is_call: bool,
+    pub selector: String,
+    pub args: Vec<String>, // Argument types, in the order of the selector
+    pub ret_value: Option<String>,
+    pub index: u32, // Where the declaration is in the code
+}
+
+#[derive(Clone, Debug)]
+pub struct Library {
+    pub name: String,
+    pub source: String,
+    pub script: bool,
+    pub exports: Option<Vec<String>>,
+    pub structs: Vec<StructLayout>,
+    pub messages: Vec<MessageEntry>,
+    pub code: Vec<Box<Expression>>,
+}
+
+impl Library {
+    pub fn from_module(m: &Module) -> Library {
+        let mut structs = vec![];
+        let mut messages = vec![];
+        for (index, inst) in m.get_code().iter().enumerate() {
+            match **inst {
+                Expression::StructDeclaration { ref name, ref members, ref parent, ref composers, .. } => structs.push(StructLayout {
+                    name: name.clone(), parent: parent.clone(), composers: composers.clone(), members: members.clone()
+                }),
+                Expression::MessageDeclaration { ref bound_struct, is_call, ref args_or_name, ref selector, ref ret_value, .. } => messages.push(MessageEntry {
+                    bound_struct: bound_struct.clone(),
+                    is_call,
+                    selector: selector.clone(),
+                    args: args_or_name.as_ref().map(|args| args.iter().map(|a| a.1.clone()).collect()).unwrap_or_default(),
+                    ret_value: ret_value.clone(),
+                    index: index as u32,
+                }),
+                _ => {}
+            }
+        }
+        Library {
+            name: m.get_full_name(),
+            source: m.get_file_name(),
+            script: m.is_script(),
+            exports: m.get_exports(),
+            structs,
+            messages,
+            code: m.get_code().clone(),
+        }
+    }
+
+    pub fn into_module(self) -> Module {
+        Module::new(&self.name, self.code).with_file(&self.source).with_script(self.script).with_exports(self.exports)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> ::std::io::Result<()> {
+        let mut out = Writer { buf: vec![] };
+        out.buf.extend_from_slice(MAGIC);
+        out.u16(FORMAT_VERSION);
+        out.string(&self.name);
+        out.string(&self.source);
+        out.u8(self.script as u8);
+        out.option(&self.exports, |out, exports| out.strings(exports));
+        out.u32(self.structs.len() as u32);
+        for s in self.structs.iter() {
+            out.string(&s.name);
+            out.option(&s.parent, |out, p| out.string(p));
+            out.strings(&s.composers);
+            out.pairs(&s.members);
+        }
+        out.u32(self.messages.len() as u32);
+        for msg in self.messages.iter() {
+            out.string(&msg.bound_struct);
+            out.u8(msg.is_call as u8);
+            out.string(&msg.selector);
+            out.strings(&msg.args);
+            out.option(&msg.ret_value, |out, r| out.string(r));
+            out.u32(msg.index);
+        }
+        out.block(&self.code);
+        w.write_all(&out.buf)
+    }
+
+    // Reads a library, checking its magic bytes and version first
+    pub fn read<R: Read>(file: &str, r: &mut R) -> Result<Library, LibraryError> {
+        let error = |kind| LibraryError { file: file.to_string(), kind };
+        let mut buf = vec![];
+        r.read_to_end(&mut buf).map_err(|e| error(LibraryErrorKind::Io(e.to_string())))?;
+        if buf.len() < MAGIC.len() || &buf[..MAGIC.len()] != MAGIC {
+            return Err(error(LibraryErrorKind::NotALibrary))
+        }
+        let mut rd = Reader { buf: &buf, pos: MAGIC.len() };
+        let version = rd.u16("version").map_err(&error)?;
+        if version != FORMAT_VERSION {
+            return Err(error(LibraryErrorKind::IncompatibleVersion(version)))
+        }
+        rd.library().map_err(error)
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn strings(&mut self, list: &[String]) {
+        self.u32(list.len() as u32);
+        for s in list.iter() {
+            self.string(s);
+        }
+    }
+
+    fn pairs(&mut self, list: &[(String, String)]) {
+        self.u32(list.len() as u32);
+        for p in list.iter() {
+            self.string(&p.0);
+            self.string(&p.1);
+        }
+    }
+
+    fn option<T, F: FnOnce(&mut Writer, &T)>(&mut self, v: &Option<T>, f: F) {
+        match *v {
+            Some(ref v) => {
+                self.u8(1);
+                f(self, v);
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn block(&mut self, block: &[Box<Expression>]) {
+        self.u32(block.len() as u32);
+        for e in block.iter() {
+            self.expression(e);
+        }
+    }
+
+    fn operator(&mut self, op: TokenType) {
+        // The parser only makes these operators, so the position is always found
+        self.u8(OPERATORS.iter().position(|&o| o == op).unwrap_or(OPERATORS.len()) as u8);
+    }
+
+    // An expression is a tag byte, then its fields in the order they are declared
+    fn expression(&mut self, e: &Expression) {
+        match *e {
+            Expression::IntegerExpression(ref s) => { self.u8(0); self.string(s); },
+            Expression::FloatExpression(ref s) => { self.u8(1); self.string(s); },
+            Expression::StringExpression(ref s) => { self.u8(2); self.string(s); },
+            Expression::BooleanExpression(b) => { self.u8(3); self.u8(b as u8); },
+            Expression::ArrayExpression(line, ref elements) => { self.u8(19); self.i32(line); self.block(elements); },
+            Expression::VariableExpression(line, ref name) => { self.u8(4); self.i32(line); self.string(name); },
+            Expression::StructExpression(line, ref name) => { self.u8(5); self.i32(line); self.string(name); },
+            Expression::PrefixExpression(line, op, ref e) => {
+                self.u8(6);
+                self.i32(line);
+                self.operator(op);
+                self.expression(e);
+            },
+            Expression::InstanceExpression { ref struct_name, ref members, line } => {
+                self.u8(7);
+                self.string(struct_name);
+                self.u32(members.len() as u32);
+                for m in members.iter() {
+                    self.string(&m.0);
+                    self.expression(&m.1);
+                }
+                self.i32(line);
+            },
+            Expression::MessageSend { ref receiver, ref args_or_name, line } => {
+                self.u8(8);
+                self.option(receiver, |out, r| out.expression(r));
+                match *args_or_name {
+                    Ok(ref args) => {
+                        self.u8(1);
+                        self.u32(args.len() as u32);
+                        for a in args.iter() {
+                            self.string(&a.0);
+                            self.expression(&a.1);
+                        }
+                    },
+                    Err(ref name) => { self.u8(0); self.string(name); }
+                }
+                self.i32(line);
+            },
+            Expression::BinaryExpression(line, op, ref l, ref r) => {
+                self.u8(9);
+                self.i32(line);
+                self.operator(op);
+                self.expression(l);
+                self.expression(r);
+            },
+            Expression::MemberExpression(line, ref e, ref name) => {
+                self.u8(10);
+                self.i32(line);
+                self.expression(e);
+                self.string(name);
+            },
+            Expression::ModuleDeclaration(line, ref name, ref exports) => {
+                self.u8(11);
+                self.i32(line);
+                self.string(name);
+                self.option(exports, |out, exports| out.strings(exports));
+            },
+            Expression::ImportDeclaration { ref module, ref names, line } => {
+                self.u8(12);
+                self.string(module);
+                self.option(names, |out, names| out.strings(names));
+                self.i32(line);
+            },
+            Expression::StructDeclaration { ref name, ref members, ref parent, ref composers, line } => {
+                self.u8(13);
+                self.string(name);
+                self.pairs(members);
+                self.option(parent, |out, p| out.string(p));
+                self.strings(composers);
+                self.i32(line);
+            },
+            Expression::MessageDeclaration { ref bound_struct, is_call, ref type_params, ref args_or_name, ref selector, ref body, ref ret_value, line } => {
+                self.u8(14);
+                self.string(bound_struct);
+                self.u8(is_call as u8);
+                self.strings(type_params);
+                match *args_or_name {
+                    Ok(ref args) => { self.u8(1); self.pairs(args); },
+                    Err(ref name) => { self.u8(0); self.string(name); }
+                }
+                self.string(selector);
+                self.block(body);
+                self.option(ret_value, |out, r| out.string(r));
+                self.i32(line);
+            },
+            Expression::MainDeclaration { ref body, line } => {
+                self.u8(15);
+                self.block(body);
+                self.i32(line);
+            },
+            Expression::LetStatement { id, ref bound_name, ref ntype, ref expression, line } => {
+                self.u8(16);
+                self.u32(id as u32);
+                self.string(bound_name);
+                self.option(ntype, |out, t| out.string(t));
+                self.expression(expression);
+                self.i32(line);
+            },
+            Expression::ReturnStatement(line, ref e) => {
+                self.u8(17);
+                self.i32(line);
+                self.expression(e);
+            },
+            Expression::IfStatement { ref condition, ref body, ref else_body, line } => {
+                self.u8(18);
+                self.expression(condition);
+                self.block(body);
+                self.option(else_body, |out, b| out.block(b));
+                self.i32(line);
+            },
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+type ReadResult<T> = Result<T, LibraryErrorKind>;
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize, what: &str) -> ReadResult<&'a [u8]> {
+        if self.buf.len() - self.pos < n {
+            return Err(LibraryErrorKind::Corrupt(what.to_string()))
+        }
+        self.pos += n;
+        Ok(&self.buf[self.pos - n..self.pos])
+    }
+
+    fn u8(&mut self, what: &str) -> ReadResult<u8> {
+        Ok(self.bytes(1, what)?[0])
+    }
+
+    fn u16(&mut self, what: &str) -> ReadResult<u16> {
+        let b = self.bytes(2, what)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self, what: &str) -> ReadResult<u32> {
+        let b = self.bytes(4, what)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self, what: &str) -> ReadResult<i32> {
+        Ok(self.u32(what)? as i32)
+    }
+
+    fn bool(&mut self, what: &str) -> ReadResult<bool> {
+        match self.u8(what)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(LibraryErrorKind::Corrupt(what.to_string()))
+        }
+    }
+
+    fn string(&mut self, what: &str) -> ReadResult<String> {
+        let len = self.u32(what)? as usize;
+        let b = self.bytes(len, what)?;
+        String::from_utf8(b.to_vec()).map_err(|_| LibraryErrorKind::Corrupt(what.to_string()))
+    }
+
+    // A list count, checked against what is left so a corrupt count cannot make a huge allocation
+    fn count(&mut self, what: &str) -> ReadResult<usize> {
+        let n = self.u32(what)? as usize;
+        if n > self.buf.len() - self.pos {
+            return Err(LibraryErrorKind::Corrupt(what.to_string()))
+        }
+        Ok(n)
+    }
+
+    fn strings(&mut self, what: &str) -> ReadResult<Vec<String>> {
+        let n = self.count(what)?;
+        (0..n).map(|_| self.string(what)).collect()
+    }
+
+    fn pairs(&mut self, what: &str) -> ReadResult<Vec<(String, String)>> {
+        let n = self.count(what)?;
+        (0..n).map(|_| Ok((self.string(what)?, self.string(what)?))).collect()
+    }
+
+    fn option<T, F: FnOnce(&mut Reader<'a>) -> ReadResult<T>>(&mut self, what: &str, f: F) -> ReadResult<Option<T>> {
+        match self.bool(what)? {
+            true => Ok(Some(f(self)?)),
+            false => Ok(None)
+        }
+    }
+
+    fn library(&mut self) -> ReadResult<Library> {
+        let name = self.string("module name")?;
+        let source = self.string("source file")?;
+        let script = self.bool("script flag")?;
+        let exports = self.option("exports", |rd| rd.strings("exports"))?;
+        let n = self.count("structs")?;
+        let mut structs = vec![];
+        for _ in 0..n {
+            structs.push(StructLayout {
+                name: self.string("struct name")?,
+                parent: self.option("struct parent", |rd| rd.string("struct parent"))?,
+                composers: self.strings("struct composers")?,
+                members: self.pairs("struct members")?,
+            });
+        }
+        let n = self.count("messages")?;
+        let mut messages = vec![];
+        for _ in 0..n {
+            messages.push(MessageEntry {
+                bound_struct: self.string("message struct")?,
+                is_call: self.bool("message kind")?,
+                selector: self.string("message selector")?,
+                args: self.strings("message arguments")?,
+                ret_value: self.option("message return type", |rd| rd.string("message return type"))?,
+                index: self.u32("message index")?,
+            });
+        }
+        let code = self.block()?;
+        if self.pos != self.buf.len() {
+            return Err(LibraryErrorKind::Corrupt("end of library".to_string()))
+        }
+        Ok(Library { name, source, script, exports, structs, messages, code })
+    }
+
+    fn block(&mut self) -> ReadResult<Vec<Box<Expression>>> {
+        let n = self.count("block")?;
+        (0..n).map(|_| self.expression()).collect()
+    }
+
+    fn operator(&mut self) -> ReadResult<TokenType> {
+        let i = self.u8("operator")? as usize;
+        OPERATORS.get(i).cloned().ok_or(LibraryErrorKind::Corrupt("operator".to_string()))
+    }
+
+    fn expression(&mut self) -> ReadResult<Box<Expression>> {
+        let e = match self.u8("expression")? {
+            0 => Expression::IntegerExpression(self.string("integer")?),
+            1 => Expression::FloatExpression(self.string("float")?),
+            2 => Expression::StringExpression(self.string("string")?),
+            3 => Expression::BooleanExpression(self.bool("boolean")?),
+            4 => Expression::VariableExpression(self.i32("line")?, self.string("variable")?),
+            5 => Expression::StructExpression(self.i32("line")?, self.string("struct")?),
+            6 => Expression::PrefixExpression(self.i32("line")?, self.operator()?, self.expression()?),
+            7 => {
+                let struct_name = self.string("struct")?;
+                let n = self.count("members")?;
+                let mut members = vec![];
+                for _ in 0..n {
+                    members.push((self.string("member")?, self.expression()?));
+                }
+                Expression::InstanceExpression { struct_name, members, line: self.i32("line")? }
+            },
+            8 => {
+                let receiver = self.option("receiver", |rd| rd.expression())?;
+                let args_or_name = match self.bool("message")? {
+                    true => {
+                        let n = self.count("arguments")?;
+                        let mut args = vec![];
+                        for _ in 0..n {
+                            args.push((self.string("keyword")?, self.expression()?));
+                        }
+                        Ok(args)
+                    },
+                    false => Err(self.string("message name")?)
+                };
+                Expression::MessageSend { receiver, args_or_name, line: self.i32("line")? }
+            },
+            9 => Expression::BinaryExpression(self.i32("line")?, self.operator()?, self.expression()?, self.expression()?),
+            10 => Expression::MemberExpression(self.i32("line")?, self.expression()?, self.string("member")?),
+            11 => Expression::ModuleDeclaration(self.i32("line")?, self.string("module name")?, self.option("exports", |rd| rd.strings("exports"))?),
+            12 => Expression::ImportDeclaration {
+                module: self.string("import")?,
+                names: self.option("import names", |rd| rd.strings("import names"))?,
+                line: self.i32("line")?,
+            },
+            13 => Expression::StructDeclaration {
+                name: self.string("struct name")?,
+                members: self.pairs("struct members")?,
+                parent: self.option("struct parent", |rd| rd.string("struct parent"))?,
+                composers: self.strings("struct composers")?,
+                line: self.i32("line")?,
+            },
+            14 => {
+                let bound_struct = self.string("message struct")?;
+                let is_call = self.bool("message kind")?;
+                let type_params = self.strings("type parameters")?;
+                let args_or_name = match self.bool("message")? {
+                    true => Ok(self.pairs("message arguments")?),
+                    false => Err(self.string("message name")?)
+                };
+                Expression::MessageDeclaration {
+                    bound_struct, is_call, type_params, args_or_name,
+                    selector: self.string("message selector")?,
+                    body: self.block()?,
+                    ret_value: self.option("message return type", |rd| rd.string("message return type"))?,
+                    line: self.i32("line")?,
+                }
+            },
+            15 => Expression::MainDeclaration { body: self.block()?, line: self.i32("line")? },
+            16 => Expression::LetStatement {
+                id: self.u32("let")? as usize,
+                bound_name: self.string("let name")?,
+                ntype: self.option("let type", |rd| rd.string("let type"))?,
+                expression: self.expression()?,
+                line: self.i32("line")?,
+            },
+            17 => Expression::ReturnStatement(self.i32("line")?, self.expression()?),
+            18 => Expression::IfStatement {
+                condition: self.expression()?,
+                body: self.block()?,
+                else_body: self.option("else", |rd| rd.block())?,
+                line: self.i32("line")?,
+            },
+            19 => Expression::ArrayExpression(self.i32("line")?, self.block()?),
+            _ => return Err(LibraryErrorKind::Corrupt("expression".to_string()))
+        };
+        Ok(Box::new(e))
+    }
+}
@@ -3,9 +3,18 @@ pub mod token;
 pub mod trie;
 pub mod parser;
 pub mod module;
-mod parslets;
+pub mod resolver;
+pub mod graph;
+pub mod symbols;
+pub mod typecheck;
+pub mod dispatch;
+pub mod library;
+pub mod parslets;
 pub mod ast;
 
-pub use self::lexer::Lexer;
+pub use self::lexer::{Lexer, LexerConfig};
 pub use self::parser::Parser;
 pub use self::module::{Module, ModuleManager};
+pub use self::resolver::Resolver;
+pub use self::symbols::SymbolTable;
+pub use self::typecheck::TypeChecker;
@@ -1,11 +1,15 @@
 // Represents a Kobold module, where all the structs go under, and a single thread of execution, with an optional "main" point that is executed if it is run
 use std::collections::HashMap;
 use super::ast::Expression;
+use super::graph::{DependencyGraph, CycleError};
 
 #[derive(Clone, Debug)]
 pub struct Module {
     name: String, // Struct complete name
     local_name: String, // Struct partial name
+    file_name: String, // Where the module was loaded from, for diagnostics
+    exports: Option<Vec<String>>, // Structs visible to other modules (None for all of them)
+    script: bool, // Loaded from a script (.ksc), whose statements are its main block
     module_code: Vec<Box<Expression>>,
 }
 
@@ -14,13 +18,84 @@ impl Module {
         Module {
             name: name.to_string(),
             local_name: name.clone().split('.').last().unwrap().to_string(),
+            file_name: name.to_string(),
+            exports: None,
+            script: false,
             module_code: module_code
         }
     }
 
+    pub fn with_exports(self, exports: Option<Vec<String>>) -> Module {
+        Module {
+            exports,
+            ..self
+        }
+    }
+
+    pub fn with_file(self, file: &str) -> Module {
+        Module {
+            file_name: file.to_string(),
+            ..self
+        }
+    }
+
+    pub fn with_script(self, script: bool) -> Module {
+        Module {
+            script,
+            ..self
+        }
+    }
+
     pub fn get_full_name(&self) -> String {
         self.name.clone()
     }
+
+    pub fn get_file_name(&self) -> String {
+        self.file_name.clone()
+    }
+
+    pub fn get_code(&self) -> &Vec<Box<Expression>> {
+        &self.module_code
+    }
+
+    // Every import/use declaration in the module, as (module, listed names, line)
+    pub fn get_imports(&self) -> Vec<(String, Option<Vec<String>>, i32)> {
+        let mut v = vec![];
+        for inst in self.module_code.iter() {
+            if let Expression::ImportDeclaration { ref module, ref names, line } = **inst {
+                v.push((module.clone(), names.clone(), line));
+            }
+        }
+        v
+    }
+
+    pub fn is_script(&self) -> bool {
+        self.script
+    }
+
+    // Std and the modules under it, which can declare the builtin types
+    pub fn is_std(&self) -> bool {
+        let name = self.get_full_name();
+        name == "Std" || name.starts_with("Std.")
+    }
+
+    pub fn get_exports(&self) -> Option<Vec<String>> {
+        self.exports.clone()
+    }
+
+    pub fn exports_struct(&self, s: &str) -> bool {
+        match self.exports {
+            Some(ref exports) => exports.iter().any(|e| e == s),
+            None => true
+        }
+    }
+
+    pub fn declares_struct(&self, s: &str) -> bool {
+        self.module_code.iter().any(|inst| match **inst {
+            Expression::StructDeclaration { ref name, .. } => name == s,
+            _ => false
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -38,32 +113,67 @@ impl ModuleNode {
     }
 
     fn add_module(&mut self, name: &str, m: Module) {
-        let key = name.clone().split('.').nth(0).unwrap();
-        let new_name = name.clone().split('.').skip(1).collect::<String>();
+        let key = name.split('.').nth(0).unwrap();
+        let new_name = name.split('.').skip(1).collect::<Vec<&str>>().join(".");
+        if self.map.is_none() {
+            self.map = Some(HashMap::new());
+        }
+        if let Some(ref mut map) = self.map {
+            let val = map.entry(key.to_string()).or_insert(ModuleNode::new());
+            if !new_name.is_empty() {
+                val.add_module(&new_name, m)
+            } else if val.module.is_some() {
+                // A node may already exist as the parent of another module (Std for Std.Number),
+                // so only a module stored on the node itself means the name is taken.
+                panic!("Name already taken: {}", m.get_full_name());
+            } else {
+                val.module = Some(m);
+            }
+        }
+    }
+
+    fn find_node(&self, name: &str) -> Option<&ModuleNode> {
+        if name.is_empty() {
+            return Some(self)
+        }
+        let key = name.split('.').nth(0).unwrap();
+        let new_name = name.split('.').skip(1).collect::<Vec<&str>>().join(".");
         match self.map {
-            Some(ref mut map) => {
-                let was_entry = map.contains_key(key);
-                let mut val = map.entry(key.to_string()).or_insert(ModuleNode::new());
-                if new_name != "" {
-                    val.add_module(&new_name, m)
-                } else if was_entry {
-                    panic!("Name already taken: {}", m.get_full_name());
-                } else {
-                    val.module = Some(m);
-                }
+            Some(ref map) => match map.get(key) {
+                Some(node) => match new_name.as_ref() {
+                    "" => Some(node),
+                    _ => node.find_node(&new_name)
+                },
+                None => None
             },
-            None => {
-                let mut map = HashMap::new();
-                let mut sub = ModuleNode::new();
-                if new_name != "" {
-                    sub.add_module(&new_name, m);
-                } else {
-                    sub.module = Some(m);
-                }
-                map.insert(key.to_string(), sub);
-                self.map = Some(map);
+            None => None
+        }
+    }
+
+    fn remove_module(&mut self, name: &str) -> Option<Module> {
+        let key = name.split('.').nth(0).unwrap();
+        let new_name = name.split('.').skip(1).collect::<Vec<&str>>().join(".");
+        match self.map {
+            Some(ref mut map) => match map.get_mut(key) {
+                Some(node) => match new_name.as_ref() {
+                    "" => node.module.take(),
+                    _ => node.remove_module(&new_name)
+                },
+                None => None
+            },
+            None => None
+        }
+    }
+
+    fn collect_modules(&self, list: &mut Vec<Module>) {
+        if let Some(ref m) = self.module {
+            list.push(m.clone());
+        }
+        if let Some(ref map) = self.map {
+            for node in map.values() {
+                node.collect_modules(list);
             }
-        };
+        }
     }
 }
 
@@ -79,11 +189,46 @@ impl ModuleManager {
         }
     }
 
+    // Looks up a module by its full dotted name (Std.Number)
     pub fn find_module(&self, s: &str) -> Option<Module> {
-        None
+        self.get_module(s).cloned()
+    }
+
+    // Like find_module, without copying the module
+    pub fn get_module(&self, s: &str) -> Option<&Module> {
+        match self.module_map.find_node(s) {
+            Some(node) => node.module.as_ref(),
+            None => None
+        }
+    }
+
+    // Lists every module under a dotted prefix (Std gives Std.Number, Std.String, ...), sorted by name.
+    // The module named by the prefix itself is included, and an empty prefix lists all modules.
+    pub fn find_modules_under(&self, prefix: &str) -> Vec<Module> {
+        let mut list = vec![];
+        if let Some(node) = self.module_map.find_node(prefix) {
+            node.collect_modules(&mut list);
+        }
+        list.sort_by_key(|m| m.get_full_name());
+        list
+    }
+
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::new(self)
+    }
+
+    // Every module, after all of the modules it imports
+    pub fn modules_in_order(&self) -> Result<Vec<Module>, CycleError> {
+        let order = self.dependency_graph().topological_order()?;
+        Ok(order.iter().filter_map(|name| self.find_module(name)).collect())
     }
 
     pub fn add_module(&mut self, s: &str, m: Module) {
         self.module_map.add_module(s, m);
     }
+
+    // Gives back the module, so that it can be changed and added again
+    pub fn remove_module(&mut self, s: &str) -> Option<Module> {
+        self.module_map.remove_module(s)
+    }
 }
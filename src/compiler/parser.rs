@@ -1,9 +1,14 @@
+use super::lexer::{SyntaxError, SyntaxErrorKind};
 use super::token::{Token, TokenType, TokenStream};
-use super::ast::Expression;
+use super::ast::{Expression, selector_name};
 use std::collections::HashMap;
 use super::parslets::{PrefixParslet, InfixParslet};
-use super::parslets::literal::{IntegerParslet, FloatParslet};
-use super::parslets::operator::{BinaryParslet, PrefixOpParslet};
+use super::parslets::literal::{IntegerParslet, FloatParslet, StringParslet, BooleanParslet, ArrayParslet};
+use super::parslets::operator::{BinaryParslet, PrefixOpParslet, GroupParslet, MemberParslet};
+use super::parslets::name::{VariableParslet, StructParslet};
+use super::parslets::message::MessageSendParslet;
+
+pub type ParseResult<T> = Result<T, SyntaxError>;
 
 pub struct Parser {
     file_name: String,
@@ -13,7 +18,8 @@ pub struct Parser {
     infixs: HashMap<TokenType, Box<InfixParslet>>,
 
     t: Vec<Token>,
-    eof: Token
+    eof: Token,
+    next_id: usize
 }
 
 impl Parser {
@@ -26,110 +32,354 @@ impl Parser {
             infixs: HashMap::new(),
 
             eof: Token::new(TokenType::EndOfFile, "").with_line(-1),
+            next_id: 0,
         };
 
         // Setup...
-        tmp.prefix(TokenType::Minus, 3);
-        tmp.prefix(TokenType::Plus, 3);
+        tmp.prefix(TokenType::Minus, 4);
+        tmp.prefix(TokenType::Plus, 4);
         tmp.register_prefix(TokenType::Integer, Box::new(IntegerParslet::new()));
         tmp.register_prefix(TokenType::Float, Box::new(FloatParslet::new()));
+        tmp.register_prefix(TokenType::CString, Box::new(StringParslet::new()));
+        tmp.register_prefix(TokenType::True, Box::new(BooleanParslet::new()));
+        tmp.register_prefix(TokenType::False, Box::new(BooleanParslet::new()));
+        tmp.register_prefix(TokenType::Identifier, Box::new(VariableParslet::new()));
+        tmp.register_prefix(TokenType::StructIdentifier, Box::new(StructParslet::new()));
+        tmp.register_prefix(TokenType::LParen, Box::new(GroupParslet::new()));
+        tmp.register_prefix(TokenType::LBracket, Box::new(MessageSendParslet::new()));
+        tmp.register_prefix(TokenType::LBrace, Box::new(ArrayParslet::new()));
 
-        tmp.binary(TokenType::Plus, 1, true);
-        tmp.binary(TokenType::Minus, 1, true);
-        tmp.binary(TokenType::Asterisk, 2, true);
-        tmp.binary(TokenType::Backslash, 2, true);
+        tmp.binary(TokenType::LessThan, 1, true);
+        tmp.binary(TokenType::GreaterThan, 1, true);
+        tmp.binary(TokenType::DoubleEqual, 1, true);
+        tmp.binary(TokenType::Plus, 2, true);
+        tmp.binary(TokenType::Minus, 2, true);
+        tmp.binary(TokenType::Asterisk, 3, true);
+        tmp.binary(TokenType::Backslash, 3, true);
+        tmp.register_infix(TokenType::Period, Box::new(MemberParslet::new(5)));
 
         tmp
     }
 
-    fn can_parse(&self) -> bool {
-        self.ts.len() > 0 || self.t.len() > 0
+    fn can_parse(&mut self) -> bool {
+        self.look_ahead(0).get_type() != TokenType::EndOfFile
+    }
+
+    fn error(&self, line: i32, kind: SyntaxErrorKind) -> SyntaxError {
+        SyntaxError { file: self.file_name.clone(), line, kind }
     }
 
-    pub fn parse_top(&mut self) -> Vec<Box<Expression>> {
+    pub fn parse_top(&mut self) -> ParseResult<Vec<Box<Expression>>> {
         let mut ev = vec![];
         while self.can_parse() {
             let ctok = self.consume();
             let be = match ctok.get_type() {
-                TokenType::Module => self.parse_module_declaration(),
-                TokenType::Struct => self.parse_struct_declaration(),
-                TokenType::Message => self.parse_message_declaration(),
-                TokenType::Let => self.parse_let_statement(),
-                _ => panic!("{}:{}: Could not parse '{}'", self.file_name, ctok.get_line(), ctok.get_string())
+                TokenType::Module => self.parse_module_declaration()?,
+                TokenType::Let => self.parse_let_statement()?,
+                TokenType::Main => Box::new(Expression::MainDeclaration { body: self.parse_block()?, line: ctok.get_line() }),
+                TokenType::Identifier if ctok.get_string() == "operator" => {
+                    self.parse_operator_declaration()?;
+                    continue
+                },
+                _ => self.parse_declaration(ctok)?
+            };
+            ev.push(be);
+        }
+        Ok(ev)
+    }
+
+    // A script (.ksc) has no module declaration or main block. Its statements run in order.
+    pub fn parse_script(&mut self) -> ParseResult<Vec<Box<Expression>>> {
+        let mut ev = vec![];
+        while self.can_parse() {
+            let ctok = self.look_ahead(0).clone();
+            let be = match ctok.get_type() {
+                TokenType::Module | TokenType::Main =>
+                    return Err(self.error(ctok.get_line(), SyntaxErrorKind::ScriptDeclaration(ctok.get_string()))),
+                TokenType::Struct | TokenType::Message | TokenType::Call | TokenType::Import | TokenType::Use => {
+                    self.consume();
+                    self.parse_declaration(ctok)?
+                },
+                TokenType::Identifier if self.is_operator_declaration() => {
+                    self.consume();
+                    self.parse_operator_declaration()?;
+                    continue
+                },
+                _ => self.parse_statement()?
             };
-            // Sooner or later, allow for dynamic parsing? (to allow for extensible operators)
             ev.push(be);
         }
-        ev
+        Ok(ev)
+    }
+
+    // operator infix <+>, where the lexer has made <+> an operator
+    fn is_operator_declaration(&mut self) -> bool {
+        self.look_ahead(0).get_string() == "operator" && self.look_ahead(1).get_string() == "infix"
+            && matches!(self.look_ahead(2).get_type(), TokenType::UserOperator(_))
+    }
+
+    fn parse_declaration(&mut self, ctok: Token) -> ParseResult<Box<Expression>> {
+        match ctok.get_type() {
+            TokenType::Struct => self.parse_struct_declaration(),
+            TokenType::Message => self.parse_message_declaration(false),
+            TokenType::Call => self.parse_message_declaration(true),
+            TokenType::Import => self.parse_import_declaration(false),
+            TokenType::Use => self.parse_import_declaration(true),
+            _ => Err(self.error(ctok.get_line(), SyntaxErrorKind::CouldNotParse(ctok.get_string())))
+        }
     }
 
-    fn parse_module_declaration(&mut self) -> Box<Expression> {
+    fn parse_module_name(&mut self) -> ParseResult<String> {
         let mut string = "".to_string();
-        let mut tok = self.consume_type(TokenType::StructIdentifier);
+        let mut tok = self.consume_type(TokenType::StructIdentifier)?;
         string = string + &tok.get_string();
         while self.match_type(TokenType::Period).is_some() {
-            tok = self.consume_type(TokenType::StructIdentifier);
+            tok = self.consume_type(TokenType::StructIdentifier)?;
             string = string + "." + &tok.get_string();
         }
-        Box::new(Expression::ModuleDeclaration(string))
+        Ok(string)
+    }
+
+    // module Std.Number (Number, Integer);
+    fn parse_module_declaration(&mut self) -> ParseResult<Box<Expression>> {
+        let line = self.look_ahead(0).get_line();
+        let string = self.parse_module_name()?;
+        let mut exports = None;
+        if self.match_type(TokenType::LParen).is_some() {
+            let mut list = vec![];
+            if self.match_type(TokenType::RParen).is_none() {
+                list.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+                while self.match_type(TokenType::Comma).is_some() {
+                    list.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+                }
+                self.consume_type(TokenType::RParen)?;
+            }
+            exports = Some(list);
+        }
+        Ok(Box::new(Expression::ModuleDeclaration(line, string, exports)))
+    }
+
+    // import Std.String;
+    // use Std.Number (Number, Integer);
+    fn parse_import_declaration(&mut self, listed: bool) -> ParseResult<Box<Expression>> {
+        let line = self.look_ahead(0).get_line();
+        let module = self.parse_module_name()?;
+        let mut names = None;
+        if listed {
+            let mut list = vec![];
+            self.consume_type(TokenType::LParen)?;
+            list.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            while self.match_type(TokenType::Comma).is_some() {
+                list.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            }
+            self.consume_type(TokenType::RParen)?;
+            names = Some(list);
+        }
+        Ok(Box::new(Expression::ImportDeclaration { module, names, line }))
+    }
+
+    fn parse_struct_declaration(&mut self) -> ParseResult<Box<Expression>> {
+        let sname = self.consume_type(TokenType::StructIdentifier)?;
+        let mut parent = None;
+        if self.match_type(TokenType::LBracket).is_some() {
+            parent = Some(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            self.consume_type(TokenType::RBracket)?;
+        }
+        let mut composers = vec![];
+        if self.match_type(TokenType::LessThan).is_some() {
+            composers.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            while self.match_type(TokenType::Comma).is_some() {
+                composers.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            }
+            self.consume_type(TokenType::GreaterThan)?;
+        }
+        self.consume_type(TokenType::LBrace)?;
+        let mut members = vec![];
+        if self.look_ahead(0).get_type() == TokenType::Identifier {
+            members.push(self.parse_member()?);
+            while self.match_type(TokenType::Comma).is_some() {
+                members.push(self.parse_member()?);
+            }
+        }
+        self.consume_type(TokenType::RBrace)?;
+        Ok(Box::new(Expression::StructDeclaration{ name: sname.get_string(), members, parent, composers, line: sname.get_line() }))
+    }
+
+    // operator infix <+> 5 left -> selector plus:
+    // The lexer has already made <+> a token for the rest of the file. From here on, a <+> b parses as
+    // [a plus: b], with the precedence of the operators (1 for comparisons up to 3 for * and /).
+    fn parse_operator_declaration(&mut self) -> ParseResult<()> {
+        let fixity = self.consume_type(TokenType::Identifier)?;
+        if fixity.get_string() != "infix" {
+            return Err(self.error(fixity.get_line(), SyntaxErrorKind::NotInfix(fixity.get_string())))
+        }
+        let symbol = self.consume();
+        let symbol_type = match symbol.get_type() {
+            TokenType::UserOperator(n) => TokenType::UserOperator(n),
+            _ => return Err(self.error(symbol.get_line(), SyntaxErrorKind::NotAnOperator(symbol.get_string())))
+        };
+        let precedence = self.consume_type(TokenType::Integer)?;
+        let precedence = match precedence.get_string().parse::<i32>() {
+            Ok(p) if p > 0 => p,
+            _ => return Err(self.error(precedence.get_line(), SyntaxErrorKind::InvalidPrecedence(symbol.get_string())))
+        };
+        let associativity = self.consume_type(TokenType::Identifier)?;
+        let left = match associativity.get_string().as_ref() {
+            "left" => true,
+            "right" => false,
+            other => return Err(self.error(associativity.get_line(), SyntaxErrorKind::InvalidAssociativity(other.to_string())))
+        };
+        self.consume_type(TokenType::Arrow)?;
+        let word = self.consume_type(TokenType::Identifier)?;
+        if word.get_string() != "selector" {
+            return Err(self.error(word.get_line(), SyntaxErrorKind::ExpectedSelector(word.get_string())))
+        }
+        let keyword = self.consume_type(TokenType::Identifier)?;
+        self.consume_type(TokenType::Colon)?;
+        self.register_infix(symbol_type, Box::new(BinaryParslet::sending(precedence, left, &keyword.get_string())));
+        Ok(())
     }
 
-    fn parse_struct_declaration(&mut self) -> Box<Expression> {
-        let sname = self.consume_type(TokenType::StructIdentifier);
-        // Read type shtuff...
-        self.consume_type(TokenType::LBrace);
-        // Read members...
-        self.consume_type(TokenType::RBrace);
-        Box::new(Expression::StructDeclaration{ name: sname.get_string() })
+    // x: Integer
+    fn parse_member(&mut self) -> ParseResult<(String, String)> {
+        let name = self.consume_type(TokenType::Identifier)?;
+        self.consume_type(TokenType::Colon)?;
+        Ok((name.get_string(), self.parse_type_name()?))
     }
 
-    fn parse_message_declaration(&mut self) -> Box<Expression> {
-        let tstruct = self.consume_type(TokenType::StructIdentifier);
-        self.consume_type(TokenType::LBracket);
-        let argname: Result<HashMap<String, String>, String>;
-        let mut name = self.consume_type(TokenType::Identifier);
+    // A type name: Integer, an array of a type (Integer[]), or a collection of types (Map(String, Integer))
+    fn parse_type_name(&mut self) -> ParseResult<String> {
+        let mut name = self.consume_type(TokenType::StructIdentifier)?.get_string();
+        if self.match_type(TokenType::LParen).is_some() {
+            let mut params = vec![self.parse_type_name()?];
+            while self.match_type(TokenType::Comma).is_some() {
+                params.push(self.parse_type_name()?);
+            }
+            self.consume_type(TokenType::RParen)?;
+            name = format!("{}({})", name, params.join(", "));
+        }
+        // Integer [abs] in a message declaration is a struct and a selector, not an array
+        while self.look_ahead(0).get_type() == TokenType::LBracket && self.look_ahead(1).get_type() == TokenType::RBracket {
+            self.consume();
+            self.consume();
+            name.push_str("[]");
+        }
+        Ok(name)
+    }
+
+    fn parse_message_declaration(&mut self, is_call: bool) -> ParseResult<Box<Expression>> {
+        let mut type_params = vec![];
+        if self.match_type(TokenType::LParen).is_some() {
+            type_params.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            while self.match_type(TokenType::Comma).is_some() {
+                type_params.push(self.consume_type(TokenType::StructIdentifier)?.get_string());
+            }
+            self.consume_type(TokenType::RParen)?;
+        }
+        let line = self.look_ahead(0).get_line();
+        let tstruct = self.parse_type_name()?;
+        self.consume_type(TokenType::LBracket)?;
+        let argname: Result<Vec<(String, String)>, String>;
+        let mut name = self.consume_type(TokenType::Identifier)?;
         match self.match_type(TokenType::Colon) {
             Some(_) => {
-                let mut args_map = HashMap::new();
-                args_map.insert(name.get_string(), self.consume_type(TokenType::StructIdentifier).get_string());
+                let mut args = vec![(name.get_string(), self.parse_type_name()?)];
                 while self.match_type(TokenType::Comma).is_some() {
-                    name = self.consume_type(TokenType::Identifier);
-                    self.consume_type(TokenType::Colon);
-                    args_map.insert(name.get_string(), self.consume_type(TokenType::StructIdentifier).get_string());
+                    name = self.consume_type(TokenType::Identifier)?;
+                    if args.iter().any(|a| a.0 == name.get_string()) {
+                        return Err(self.error(name.get_line(), SyntaxErrorKind::DuplicateKeyword(name.get_string(), tstruct)))
+                    }
+                    self.consume_type(TokenType::Colon)?;
+                    args.push((name.get_string(), self.parse_type_name()?));
                 }
-                argname = Ok(args_map);
+                argname = Ok(args);
             }, // It's a list
             None => argname = Err(name.get_string()) // It's just a name
         };
-        self.consume_type(TokenType::RBracket);
+        self.consume_type(TokenType::RBracket)?;
         let mut ret_type = None;
         if let Some(_) = self.match_type(TokenType::Arrow) {
-            ret_type = Some(self.consume_type(TokenType::StructIdentifier).get_string());
+            ret_type = Some(self.parse_type_name()?);
         }
-        self.consume_type(TokenType::LBrace);
-        // Read block
-        self.consume_type(TokenType::RBrace);
-        Box::new(Expression::MessageDeclaration {
-            bound_struct: tstruct.get_string(),
+        let body = self.parse_block()?;
+        Ok(Box::new(Expression::MessageDeclaration {
+            bound_struct: tstruct,
+            is_call,
+            type_params,
+            selector: selector_name(&argname),
             args_or_name: argname,
-            ret_value: ret_type
-        })
+            body,
+            ret_value: ret_type,
+            line
+        }))
     }
 
-    fn parse_let_statement(&mut self) -> Box<Expression> {
+    fn parse_let_statement(&mut self) -> ParseResult<Box<Expression>> {
         // Maybe introduce pattern matching...
-        let name = self.consume_type(TokenType::Identifier);
+        let name = self.consume_type(TokenType::Identifier)?;
         let mut name_type = None;
         if let Some(_) = self.match_type(TokenType::Colon) {
-            name_type = Some(self.consume_type(TokenType::StructIdentifier).get_string());
+            name_type = Some(self.parse_type_name()?);
         }
-        self.consume_type(TokenType::Equal);
-        let expr = self.parse_expression(0);
-        Box::new(Expression::LetStatement {
+        self.consume_type(TokenType::Equal)?;
+        let expr = self.parse_expression(0)?;
+        Ok(Box::new(Expression::LetStatement {
+            id: self.new_id(),
             bound_name: name.get_string(),
             ntype: name_type,
-            expression: expr
-        })
+            expression: expr,
+            line: name.get_line()
+        }))
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    // { statement... }
+    fn parse_block(&mut self) -> ParseResult<Vec<Box<Expression>>> {
+        let mut stmts = vec![];
+        self.consume_type(TokenType::LBrace)?;
+        while self.match_type(TokenType::RBrace).is_none() {
+            if !self.can_parse() {
+                let line = self.look_ahead(0).get_line();
+                return Err(self.error(line, SyntaxErrorKind::UnclosedBlock))
+            }
+            stmts.push(self.parse_statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<Box<Expression>> {
+        let tt = self.look_ahead(0).get_type();
+        match tt {
+            TokenType::Let => {
+                self.consume();
+                self.parse_let_statement()
+            },
+            TokenType::Return => {
+                let tok = self.consume();
+                Ok(Box::new(Expression::ReturnStatement(tok.get_line(), self.parse_expression(0)?)))
+            },
+            TokenType::If => {
+                let tok = self.consume();
+                self.parse_if_statement(tok.get_line())
+            },
+            _ => self.parse_expression(0)
+        }
+    }
+
+    // if condition then { ... } else { ... }
+    fn parse_if_statement(&mut self, line: i32) -> ParseResult<Box<Expression>> {
+        let condition = self.parse_expression(0)?;
+        self.consume_type(TokenType::Then)?;
+        let body = self.parse_block()?;
+        let mut else_body = None;
+        if self.match_type(TokenType::Else).is_some() {
+            else_body = Some(self.parse_block()?);
+        }
+        Ok(Box::new(Expression::IfStatement { condition, body, else_body, line }))
     }
 
     // Expression parsing
@@ -149,7 +399,7 @@ impl Parser {
         self.infixs.insert(tt, p);
     }
 
-    pub fn parse_expression(&mut self, precedence: i32) -> Box<Expression> {
+    pub fn parse_expression(&mut self, precedence: i32) -> ParseResult<Box<Expression>> {
         let tok = self.consume();
         let mut parslet: Option<Box<PrefixParslet>> = None;
         match self.prefixs.get(&tok.get_type()) {
@@ -157,8 +407,8 @@ impl Parser {
             None => {}
         };
         let mut left = match parslet {
-            None => panic!("{}:{}: Could not parse '{}':{:?}", self.file_name, tok.get_line(), tok.get_string(), tok.get_type()),
-            Some(plt) => plt.parse(self, tok)
+            None => return Err(self.error(tok.get_line(), SyntaxErrorKind::CouldNotParse(tok.get_string()))),
+            Some(plt) => plt.parse(self, tok)?
         };
         let mut gprec = self.get_precedence();
         while precedence < gprec {
@@ -174,12 +424,12 @@ impl Parser {
                 None => left,
                 Some(plt) => {
                     let ntok = self.consume();
-                    plt.parse(self, left, ntok)
+                    plt.parse(self, left, ntok)?
                 }
             };
             gprec = self.get_precedence();
         }
-        left
+        Ok(left)
     }
 
     fn get_precedence(&mut self) -> i32 {
@@ -190,7 +440,7 @@ impl Parser {
         }
     }
 
-    fn match_type(&mut self, expect: TokenType) -> Option<Token> {
+    pub fn match_type(&mut self, expect: TokenType) -> Option<Token> {
         {
             let tok = self.look_ahead(0);
             if tok.get_type() != expect {
@@ -200,25 +450,26 @@ impl Parser {
         Some(self.consume())
     }
 
-    fn consume_type(&mut self, expect: TokenType) -> Token {
-        {
+    pub fn consume_type(&mut self, expect: TokenType) -> ParseResult<Token> {
+        let (found, line) = {
             let tok = self.look_ahead(0);
-            if tok.get_type() != expect {
-                panic!("Token type mismatch: {:?} expected, {:?} received at line {}", expect, tok.get_type(), tok.get_line());
-            }
+            (tok.get_type(), tok.get_line())
+        };
+        if found != expect {
+            return Err(self.error(line, SyntaxErrorKind::Unexpected(expect, found)))
         }
-        self.consume()
+        Ok(self.consume())
     }
 
-    fn consume(&mut self) -> Token {
-        self.look_ahead(0);
-        match self.t.len() > 0 {
-            true => self.t.remove(0),
-            false => self.eof.clone()
+    // The EndOfFile of the stream is never consumed, so that errors past the end have its line
+    pub fn consume(&mut self) -> Token {
+        match self.look_ahead(0).get_type() {
+            TokenType::EndOfFile => self.look_ahead(0).clone(),
+            _ => self.t.remove(0)
         }
     }
 
-    fn look_ahead(&mut self, x: usize) -> &Token {
+    pub fn look_ahead(&mut self, x: usize) -> &Token {
         let tlen = self.t.len();
         if tlen <= x {
             self.t.append(&mut self.ts.read((x + 1) - tlen));
@@ -1,13 +1,13 @@
 use super::PrefixParslet;
 use super::super::ast::Expression;
-use super::super::token::Token;
-use super::super::parser::Parser;
+use super::super::token::{Token, TokenType};
+use super::super::parser::{Parser, ParseResult};
 
 pub struct IntegerParslet;
 impl IntegerParslet { pub fn new() -> IntegerParslet { IntegerParslet } }
 impl PrefixParslet for IntegerParslet {
-    fn parse(&self, _: &mut Parser, token: Token) -> Box<Expression> {
-        Box::new(Expression::IntegerExpression(token.get_string()))
+    fn parse(&self, _: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        Ok(Box::new(Expression::IntegerExpression(token.get_string())))
     }
     fn dup(&self) -> Box<PrefixParslet> { Box::new(IntegerParslet) }
 }
@@ -16,8 +16,44 @@ pub struct FloatParslet;
 impl FloatParslet { pub fn new() -> FloatParslet { FloatParslet } }
 impl PrefixParslet for FloatParslet {
     #[allow(unused_variables)]
-    fn parse(&self, parser: &mut Parser, token: Token) -> Box<Expression> {
-        Box::new(Expression::FloatExpression(token.get_string()))
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        Ok(Box::new(Expression::FloatExpression(token.get_string())))
     }
     fn dup(&self) -> Box<PrefixParslet> { Box::new(FloatParslet) }
 }
+
+pub struct StringParslet;
+impl StringParslet { pub fn new() -> StringParslet { StringParslet } }
+impl PrefixParslet for StringParslet {
+    fn parse(&self, _: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        Ok(Box::new(Expression::StringExpression(token.get_string())))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(StringParslet) }
+}
+
+pub struct BooleanParslet;
+impl BooleanParslet { pub fn new() -> BooleanParslet { BooleanParslet } }
+impl PrefixParslet for BooleanParslet {
+    fn parse(&self, _: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        Ok(Box::new(Expression::BooleanExpression(token.get_type() == TokenType::True)))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(BooleanParslet) }
+}
+
+// An array: {1, 2, 3}. Brackets are taken by message sends, [isScript] could be either.
+pub struct ArrayParslet;
+impl ArrayParslet { pub fn new() -> ArrayParslet { ArrayParslet } }
+impl PrefixParslet for ArrayParslet {
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        let mut elements = vec![];
+        if parser.match_type(TokenType::RBrace).is_none() {
+            elements.push(parser.parse_expression(0)?);
+            while parser.match_type(TokenType::Comma).is_some() {
+                elements.push(parser.parse_expression(0)?);
+            }
+            parser.consume_type(TokenType::RBrace)?;
+        }
+        Ok(Box::new(Expression::ArrayExpression(token.get_line(), elements)))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(ArrayParslet) }
+}
@@ -0,0 +1,38 @@
+use super::PrefixParslet;
+use super::super::ast::Expression;
+use super::super::token::{Token, TokenType};
+use super::super::parser::{Parser, ParseResult};
+
+// [receiver name], [receiver key: value, other: value], or the same without a receiver
+pub struct MessageSendParslet;
+impl MessageSendParslet { pub fn new() -> MessageSendParslet { MessageSendParslet } }
+impl PrefixParslet for MessageSendParslet {
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        let implicit = parser.look_ahead(0).get_type() == TokenType::Identifier
+            && matches!(parser.look_ahead(1).get_type(), TokenType::Colon | TokenType::RBracket);
+        let receiver = match implicit {
+            true => None,
+            false => Some(parser.parse_expression(0)?)
+        };
+        let name = parser.consume_type(TokenType::Identifier)?;
+        let args_or_name = match parser.match_type(TokenType::Colon) {
+            Some(_) => {
+                let mut args = vec![(name.get_string(), parser.parse_expression(0)?)];
+                while parser.match_type(TokenType::Comma).is_some() {
+                    let name = parser.consume_type(TokenType::Identifier)?;
+                    parser.consume_type(TokenType::Colon)?;
+                    args.push((name.get_string(), parser.parse_expression(0)?));
+                }
+                Ok(args)
+            },
+            None => Err(name.get_string())
+        };
+        parser.consume_type(TokenType::RBracket)?;
+        Ok(Box::new(Expression::MessageSend {
+            receiver,
+            args_or_name,
+            line: token.get_line()
+        }))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(MessageSendParslet) }
+}
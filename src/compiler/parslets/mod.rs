@@ -1,17 +1,19 @@
 pub mod literal;
 pub mod operator;
+pub mod name;
+pub mod message;
 
 use super::ast::Expression;
 use super::token::Token;
-use super::parser::Parser;
+use super::parser::{Parser, ParseResult};
 
 pub trait PrefixParslet {
-    fn parse(&self, parser: &mut Parser, token: Token) -> Box<Expression>;
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>>;
     fn dup(&self) -> Box<PrefixParslet>;
 }
 
 pub trait InfixParslet {
-    fn parse(&self, parser: &mut Parser, left: Box<Expression>, token: Token) -> Box<Expression>;
+    fn parse(&self, parser: &mut Parser, left: Box<Expression>, token: Token) -> ParseResult<Box<Expression>>;
     fn dup(&self) -> Box<InfixParslet>;
     fn get_precedence(&self) -> i32;
 }
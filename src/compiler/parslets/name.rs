@@ -0,0 +1,42 @@
+use super::PrefixParslet;
+use super::super::ast::Expression;
+use super::super::token::{Token, TokenType};
+use super::super::parser::{Parser, ParseResult};
+
+pub struct VariableParslet;
+impl VariableParslet { pub fn new() -> VariableParslet { VariableParslet } }
+impl PrefixParslet for VariableParslet {
+    fn parse(&self, _: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        Ok(Box::new(Expression::VariableExpression(token.get_line(), token.get_string())))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(VariableParslet) }
+}
+
+// Main, or an instance of it: Main {x: 1, y: 2}
+pub struct StructParslet;
+impl StructParslet { pub fn new() -> StructParslet { StructParslet } }
+impl PrefixParslet for StructParslet {
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        if parser.match_type(TokenType::LBrace).is_none() {
+            return Ok(Box::new(Expression::StructExpression(token.get_line(), token.get_string())))
+        }
+        let mut members = vec![];
+        if parser.match_type(TokenType::RBrace).is_none() {
+            loop {
+                let name = parser.consume_type(TokenType::Identifier)?;
+                parser.consume_type(TokenType::Colon)?;
+                members.push((name.get_string(), parser.parse_expression(0)?));
+                if parser.match_type(TokenType::Comma).is_none() {
+                    break
+                }
+            }
+            parser.consume_type(TokenType::RBrace)?;
+        }
+        Ok(Box::new(Expression::InstanceExpression {
+            struct_name: token.get_string(),
+            members,
+            line: token.get_line()
+        }))
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(StructParslet) }
+}
@@ -1,8 +1,8 @@
 use super::PrefixParslet;
 use super::InfixParslet;
 use super::super::ast::Expression;
-use super::super::token::{Token/*, TokenType*/};
-use super::super::parser::Parser;
+use super::super::token::{Token, TokenType};
+use super::super::parser::{Parser, ParseResult};
 
 pub struct PrefixOpParslet {
     precedence: i32,
@@ -15,34 +15,85 @@ impl PrefixOpParslet {
     }
 }
 impl PrefixParslet for PrefixOpParslet {
-    fn parse(&self, parser: &mut Parser, token: Token) -> Box<Expression> {
-        let expr = parser.parse_expression(self.precedence);
-        Box::new(Expression::PrefixExpression(token.get_line(), token.get_type(), expr))
+    fn parse(&self, parser: &mut Parser, token: Token) -> ParseResult<Box<Expression>> {
+        let expr = parser.parse_expression(self.precedence)?;
+        Ok(Box::new(Expression::PrefixExpression(token.get_line(), token.get_type(), expr)))
     }
     fn dup(&self) -> Box<PrefixParslet> { Box::new(PrefixOpParslet::new(self.precedence)) }
 }
 
+// left op right. The operators declared by modules send a message instead: [left selector: right]
 pub struct BinaryParslet {
     precedence: i32,
-    left_rec: bool
+    left_rec: bool,
+    selector: Option<String>,
 }
 impl BinaryParslet {
     pub fn new(p: i32, lr: bool) -> BinaryParslet {
         BinaryParslet {
             precedence: p,
-            left_rec: lr
+            left_rec: lr,
+            selector: None,
+        }
+    }
+
+    pub fn sending(p: i32, lr: bool, keyword: &str) -> BinaryParslet {
+        BinaryParslet {
+            selector: Some(keyword.to_string()),
+            ..BinaryParslet::new(p, lr)
         }
     }
 }
 impl InfixParslet for BinaryParslet {
-    fn parse(&self, parser: &mut Parser, left: Box<Expression>, token: Token) -> Box<Expression> {
+    fn parse(&self, parser: &mut Parser, left: Box<Expression>, token: Token) -> ParseResult<Box<Expression>> {
         let prec = match self.left_rec {
             true => self.precedence,
             false => self.precedence - 1,
         };
-        let right = parser.parse_expression(prec);
-        Box::new(Expression::BinaryExpression(token.get_line(), token.get_type(), left, right))
+        let right = parser.parse_expression(prec)?;
+        Ok(match self.selector {
+            Some(ref keyword) => Box::new(Expression::MessageSend {
+                receiver: Some(left),
+                args_or_name: Ok(vec![(keyword.clone(), right)]),
+                line: token.get_line()
+            }),
+            None => Box::new(Expression::BinaryExpression(token.get_line(), token.get_type(), left, right))
+        })
+    }
+    fn get_precedence(&self) -> i32 { self.precedence }
+    fn dup(&self) -> Box<InfixParslet> {
+        Box::new(BinaryParslet { precedence: self.precedence, left_rec: self.left_rec, selector: self.selector.clone() })
+    }
+}
+
+// ( expression )
+pub struct GroupParslet;
+impl GroupParslet { pub fn new() -> GroupParslet { GroupParslet } }
+impl PrefixParslet for GroupParslet {
+    fn parse(&self, parser: &mut Parser, _: Token) -> ParseResult<Box<Expression>> {
+        let expr = parser.parse_expression(0)?;
+        parser.consume_type(TokenType::RParen)?;
+        Ok(expr)
+    }
+    fn dup(&self) -> Box<PrefixParslet> { Box::new(GroupParslet) }
+}
+
+// expression.member
+pub struct MemberParslet {
+    precedence: i32,
+}
+impl MemberParslet {
+    pub fn new(p: i32) -> MemberParslet {
+        MemberParslet {
+            precedence: p
+        }
+    }
+}
+impl InfixParslet for MemberParslet {
+    fn parse(&self, parser: &mut Parser, left: Box<Expression>, token: Token) -> ParseResult<Box<Expression>> {
+        let name = parser.consume_type(TokenType::Identifier)?;
+        Ok(Box::new(Expression::MemberExpression(token.get_line(), left, name.get_string())))
     }
     fn get_precedence(&self) -> i32 { self.precedence }
-    fn dup(&self) -> Box<InfixParslet> { return Box::new(BinaryParslet::new(self.precedence, self.left_rec)) }
+    fn dup(&self) -> Box<InfixParslet> { Box::new(MemberParslet::new(self.precedence)) }
 }
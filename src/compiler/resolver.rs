@@ -0,0 +1,185 @@
+// Resolves struct names across modules, using the import/use declarations of a module
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use super::ast::Expression;
+use super::module::{Module, ModuleManager};
+use super::typecheck::{Type, structured_type};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolveErrorKind {
+    UnknownModule(String), // import of a module that was never loaded
+    UnknownName(String, String), // use of a name (1) that the module (0) does not declare
+    UnknownStruct(String), // reference to a struct that is neither declared nor imported
+    NotExported(String, String), // the module (0) declares the struct (1), but does not export it
+    Ambiguous(String, Vec<String>), // name (0) is imported from all of the modules in (1)
+    Builtin(String), // declaration of a struct named like a builtin type, outside of the Std modules
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolveError {
+    pub file: String,
+    pub line: i32,
+    pub kind: ResolveErrorKind,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self.kind {
+            ResolveErrorKind::UnknownModule(ref m) =>
+                write!(fmt, "{}:{}: Unknown module {}", self.file, self.line, m),
+            ResolveErrorKind::UnknownName(ref m, ref n) =>
+                write!(fmt, "{}:{}: Module {} has no struct named {}", self.file, self.line, m, n),
+            ResolveErrorKind::UnknownStruct(ref n) =>
+                write!(fmt, "{}:{}: Unknown struct {}", self.file, self.line, n),
+            ResolveErrorKind::NotExported(ref m, ref n) =>
+                write!(fmt, "{}:{}: Struct {} is private to module {}, add it to the export list of {} to use it here", self.file, self.line, n, m, m),
+            ResolveErrorKind::Ambiguous(ref n, ref ms) =>
+                write!(fmt, "{}:{}: Ambiguous name {}, imported from {}", self.file, self.line, n, ms.join(", ")),
+            ResolveErrorKind::Builtin(ref n) =>
+                write!(fmt, "{}:{}: {} is a builtin type, only the Std modules can declare it", self.file, self.line, n),
+        }
+    }
+}
+
+pub struct Resolver<'a> {
+    mman: &'a ModuleManager,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(mman: &'a ModuleManager) -> Resolver<'a> {
+        Resolver { mman }
+    }
+
+    // Finds the full name of the module that declares the struct `name`, as seen from the module `m`.
+    // Local declarations come first, then names listed with use, then every struct of an imported module.
+    // Structs of other modules are only visible if their module exports them.
+    pub fn resolve_struct(&self, m: &Module, name: &str, line: i32) -> Result<String, ResolveError> {
+        if m.declares_struct(name) {
+            return Ok(m.get_full_name())
+        }
+        let imports = m.get_imports();
+        let listed = imports.iter()
+            .filter(|imp| imp.1.as_ref().is_some_and(|n| n.iter().any(|n| n == name)))
+            .map(|imp| imp.0.clone())
+            .collect::<Vec<String>>();
+        let glob = imports.iter()
+            .filter(|imp| imp.1.is_none())
+            .map(|imp| imp.0.clone())
+            .collect::<Vec<String>>();
+
+        let mut hidden = None;
+        for candidates in [listed, glob] {
+            let mut found = vec![];
+            for module in candidates {
+                match self.mman.find_module(&module) {
+                    Some(ref im) if im.declares_struct(name) && im.exports_struct(name) => found.push(module),
+                    Some(ref im) if im.declares_struct(name) => hidden = hidden.or(Some(module)),
+                    _ => {}
+                }
+            }
+            found.sort();
+            found.dedup();
+            match found.len() {
+                0 => {},
+                1 => return Ok(found.remove(0)),
+                _ => return Err(self.error(m, line, ResolveErrorKind::Ambiguous(name.to_string(), found)))
+            }
+        }
+        match hidden {
+            Some(module) => Err(self.error(m, line, ResolveErrorKind::NotExported(module, name.to_string()))),
+            None => Err(self.error(m, line, ResolveErrorKind::UnknownStruct(name.to_string())))
+        }
+    }
+
+    // Checks the imports of a module, and every struct name it refers to
+    pub fn check_module(&self, m: &Module) -> Vec<ResolveError> {
+        let mut errors = vec![];
+        if !m.is_std() {
+            for inst in m.get_code().iter() {
+                if let Expression::StructDeclaration { ref name, line, .. } = **inst {
+                    if Type::builtin(name).is_some() {
+                        errors.push(self.error(m, line, ResolveErrorKind::Builtin(name.clone())));
+                    }
+                }
+            }
+        }
+        let mut seen: Vec<(String, String)> = vec![]; // (name, module) of every listed name
+        for (module, names, line) in m.get_imports() {
+            match self.mman.find_module(&module) {
+                Some(im) => {
+                    for name in names.unwrap_or(vec![]) {
+                        if !im.declares_struct(&name) {
+                            errors.push(self.error(m, line, ResolveErrorKind::UnknownName(module.clone(), name)));
+                            continue;
+                        }
+                        if !im.exports_struct(&name) {
+                            errors.push(self.error(m, line, ResolveErrorKind::NotExported(module.clone(), name)));
+                            continue;
+                        }
+                        let mut clash = seen.iter()
+                            .filter(|s| s.0 == name && s.1 != module)
+                            .map(|s| s.1.clone())
+                            .collect::<Vec<String>>();
+                        if !clash.is_empty() {
+                            clash.push(module.clone());
+                            errors.push(self.error(m, line, ResolveErrorKind::Ambiguous(name.clone(), clash)));
+                        }
+                        seen.push((name, module.clone()));
+                    }
+                },
+                None => errors.push(self.error(m, line, ResolveErrorKind::UnknownModule(module.clone())))
+            }
+        }
+        if !errors.is_empty() {
+            // Resolving references against broken imports only repeats the same errors
+            return errors
+        }
+
+        for (name, line) in struct_references(m) {
+            if let Err(e) = self.resolve_struct(m, &name, line) {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    fn error(&self, m: &Module, line: i32, kind: ResolveErrorKind) -> ResolveError {
+        ResolveError { file: m.get_file_name(), line, kind }
+    }
+}
+
+// Every struct name used in the declarations of a module, with the line it is used on.
+// Built in types are left out, they need no import.
+fn struct_references(m: &Module) -> Vec<(String, i32)> {
+    let mut refs = vec![];
+    for inst in m.get_code().iter() {
+        match **inst {
+            Expression::StructDeclaration { ref members, ref parent, ref composers, line, .. } => {
+                refs.extend(parent.iter().chain(composers.iter()).map(|s| (s.clone(), line)));
+                refs.extend(members.iter().flat_map(|mb| type_names(&mb.1)).map(|name| (name, line)));
+            },
+            Expression::MessageDeclaration { ref bound_struct, ref type_params, ref args_or_name, ref ret_value, line, .. } => {
+                let mut types = vec![bound_struct.clone()];
+                if let Ok(ref args) = *args_or_name {
+                    types.extend(args.iter().map(|a| a.1.clone()));
+                }
+                types.extend(ret_value.iter().cloned());
+                // Generic type names stand for whatever the sender uses
+                refs.extend(types.iter().flat_map(|t| type_names(t)).filter(|t| !type_params.contains(t)).map(|t| (t, line)));
+            },
+            Expression::LetStatement { ntype: Some(ref ntype), line, .. } => refs.extend(type_names(ntype).into_iter().map(|t| (t, line))),
+            _ => {}
+        }
+    }
+    refs.retain(|r| Type::builtin(&r.0).is_none());
+    refs
+}
+
+// The names in a type name: String and Integer in Map(String, Integer[])
+fn type_names(name: &str) -> Vec<String> {
+    let mut names = vec![];
+    let _ = structured_type::<(), _>(name, &mut |n| {
+        names.push(n.to_string());
+        Ok(Type::Nothing)
+    });
+    names
+}
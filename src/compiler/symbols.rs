@@ -0,0 +1,104 @@
+// The top-level declarations of a module: structs, messages and calls, with the line they are declared on
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use super::ast::Expression;
+use super::module::Module;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SymbolKind {
+    Struct,
+    Message,
+    Call,
+    Main,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DuplicateError {
+    pub file: String,
+    pub kind: SymbolKind,
+    pub name: String, // Struct name, or Struct [selector] for messages and calls
+    pub first_line: i32,
+    pub line: i32,
+}
+
+impl Display for DuplicateError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let kind = match self.kind {
+            SymbolKind::Struct => "struct",
+            SymbolKind::Message => "message",
+            SymbolKind::Call => "call",
+            SymbolKind::Main => "main",
+        };
+        write!(fmt, "{}:{}: Duplicate {} {}, first declared at {}:{}", self.file, self.line, kind, self.name, self.file, self.first_line)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymbolTable {
+    structs: BTreeMap<String, i32>,
+    messages: BTreeMap<(String, String), i32>, // (struct, selector)
+    calls: BTreeMap<(String, String), i32>, // (struct, selector)
+    main: Option<i32>,
+}
+
+impl SymbolTable {
+    // Collects the declarations of a module. Every declaration after the first with the same name is reported.
+    pub fn collect(m: &Module) -> (SymbolTable, Vec<DuplicateError>) {
+        let mut table = SymbolTable {
+            structs: BTreeMap::new(),
+            messages: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            main: None,
+        };
+        let mut errors = vec![];
+        for inst in m.get_code().iter() {
+            match **inst {
+                Expression::StructDeclaration { ref name, line, .. } => {
+                    match table.structs.get(name) {
+                        Some(&first_line) => errors.push(DuplicateError {
+                            file: m.get_file_name(), kind: SymbolKind::Struct, name: name.clone(), first_line, line
+                        }),
+                        None => { table.structs.insert(name.clone(), line); }
+                    }
+                },
+                Expression::MessageDeclaration { ref bound_struct, is_call, ref selector, line, .. } => {
+                    let key = (bound_struct.clone(), selector.clone());
+                    let (kind, map) = match is_call {
+                        true => (SymbolKind::Call, &mut table.calls),
+                        false => (SymbolKind::Message, &mut table.messages),
+                    };
+                    match map.get(&key) {
+                        Some(&first_line) => errors.push(DuplicateError {
+                            file: m.get_file_name(), kind, name: format!("{} [{}]", key.0, key.1), first_line, line
+                        }),
+                        None => { map.insert(key, line); }
+                    }
+                },
+                Expression::MainDeclaration { line, .. } => match table.main {
+                    Some(first_line) => errors.push(DuplicateError {
+                        file: m.get_file_name(), kind: SymbolKind::Main, name: "block".to_string(), first_line, line
+                    }),
+                    None => table.main = Some(line)
+                },
+                _ => {}
+            }
+        }
+        (table, errors)
+    }
+
+    pub fn get_struct(&self, name: &str) -> Option<i32> {
+        self.structs.get(name).cloned()
+    }
+
+    pub fn get_message(&self, bound_struct: &str, selector: &str) -> Option<i32> {
+        self.messages.get(&(bound_struct.to_string(), selector.to_string())).cloned()
+    }
+
+    pub fn get_main(&self) -> Option<i32> {
+        self.main
+    }
+
+    pub fn get_call(&self, bound_struct: &str, selector: &str) -> Option<i32> {
+        self.calls.get(&(bound_struct.to_string(), selector.to_string())).cloned()
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TokenType {
     EndOfFile,
@@ -18,6 +20,7 @@ pub enum TokenType {
     LessThan, // <
     GreaterThan, // >
     Equal,
+    DoubleEqual, // ==
     Plus,
     Asterisk,
     Backslash,
@@ -27,6 +30,7 @@ pub enum TokenType {
     LParen,
     RParen,
     Period,
+    UserOperator(usize), // Declared by the module: the number of the declaration in the file
 
     // keywords
     Module,
@@ -35,6 +39,15 @@ pub enum TokenType {
     If,
     Inner,
     Message,
+    Call,
+    Import,
+    Use,
+    Return,
+    Then,
+    Else,
+    True,
+    False,
+    Main,
 }
 
 #[derive(Debug, Clone)]
@@ -78,33 +91,27 @@ impl Token {
 // All Tokens in a TokenStream belong to the TokenStream
 #[derive(Debug, Clone)]
 pub struct TokenStream {
-    toks: Vec<Token>,
-    index: usize
+    toks: VecDeque<Token>,
 }
 
 impl TokenStream {
     pub fn new() -> TokenStream {
         TokenStream {
-            toks: vec![],
-            index: 0
+            toks: VecDeque::new(),
         }
     }
 
     pub fn len(&self) -> usize {
-        self.toks.len() - self.index
+        self.toks.len()
     }
 
     pub fn add(&mut self, t: Token) {
-        self.toks.push(t)
+        self.toks.push_back(t)
     }
 
+    // Takes the next tokens, at most size of them
     pub fn read(&mut self, size: usize) -> Vec<Token> {
-        let mut v = vec![];
-        for _ in 0..size {
-            if self.toks.len() > 0 {
-                v.push(self.toks.remove(0));
-            } else { break }
-        }
-        v
+        let size = size.min(self.toks.len());
+        self.toks.drain(..size).collect()
     }
 }
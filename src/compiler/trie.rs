@@ -12,7 +12,7 @@ enum TrieNode<'a, R> where R: Clone + PartialEq<R> {
 #[derive(Clone)]
 pub struct Trie<'a, R> where R: Clone + PartialEq<R> {
     list: Vec<TrieNode<'a, R>>,
-    index: Arc<&'a Fn(char) -> i32>
+    index: Arc<dyn Fn(char) -> i32 + 'a>
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -35,7 +35,7 @@ impl<'a, R> Debug for Trie<'a, R>
 
 impl<'a, R> Trie<'a, R>
     where R: Clone + PartialEq<R> {
-    pub fn new(hspace: usize, f: &Fn(char) -> i32) -> Trie<R> {
+    pub fn new<F: Fn(char) -> i32 + 'a>(hspace: usize, f: F) -> Trie<'a, R> {
         let mut tmp = Trie {
             list: Vec::with_capacity(hspace),
             index: Arc::new(f)
@@ -47,7 +47,7 @@ impl<'a, R> Trie<'a, R>
         tmp
     }
 
-    fn new_with_arc(hspace: usize, f: Arc<&Fn(char) -> i32>) -> Trie<R> {
+    fn new_with_arc(hspace: usize, f: Arc<dyn Fn(char) -> i32 + 'a>) -> Trie<'a, R> {
         let mut tmp = Trie {
             list: Vec::with_capacity(hspace),
             index: f
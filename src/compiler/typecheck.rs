@@ -0,0 +1,556 @@
+// Static type checking: infers the type of every expression, and checks it against let annotations,
+// declared return types, struct members and the argument types of message declarations
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use super::ast::{Expression, selector_name};
+use super::module::{Module, ModuleManager};
+use super::resolver::ResolveErrorKind;
+use super::dispatch::{Dispatch, Dispatcher, DispatchError};
+use super::token::TokenType;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    Integer,
+    Float,
+    Boolean,
+    String,
+    Nothing, // What a message without a return type returns
+    Instance(String, String), // An instance of a struct, as (module, struct)
+    Class(Box<Type>), // The struct itself, the receiver of calls
+    Variable(String), // A generic type name of a message, bound at each send. Empty for any type.
+    Array(Box<Type>), // Integer[]
+    Map(Box<Type>, Box<Type>), // Map(String, Integer)
+    Set(Box<Type>), // Set(Integer)
+}
+
+impl Type {
+    // The types built into the language, which need no declaration or import
+    pub fn builtin(name: &str) -> Option<Type> {
+        match name {
+            "Integer" => Some(Type::Integer),
+            "Float" | "Decimal" => Some(Type::Float),
+            "Boolean" => Some(Type::Boolean),
+            "String" => Some(Type::String),
+            "Nothing" => Some(Type::Nothing),
+            "Array" => Some(Type::Array(Box::new(Type::any()))),
+            "Map" => Some(Type::Map(Box::new(Type::any()), Box::new(Type::any()))),
+            "Set" => Some(Type::Set(Box::new(Type::any()))),
+            _ => None
+        }
+    }
+
+    // Stands for any type in a collection: Array is an array of anything
+    pub fn any() -> Type {
+        Type::Variable(String::new())
+    }
+
+    // The form of a collection type for any element: Array for Integer[]. Messages declared for every
+    // element type (message(T) T[] [...]) are found under it.
+    pub fn generic(&self) -> Type {
+        match *self {
+            Type::Array(_) => Type::Array(Box::new(Type::any())),
+            Type::Map(..) => Type::Map(Box::new(Type::any()), Box::new(Type::any())),
+            Type::Set(_) => Type::Set(Box::new(Type::any())),
+            ref t => t.clone()
+        }
+    }
+
+    pub fn has_variables(&self) -> bool {
+        match *self {
+            Type::Variable(_) => true,
+            Type::Class(ref t) | Type::Array(ref t) | Type::Set(ref t) => t.has_variables(),
+            Type::Map(ref k, ref v) => k.has_variables() || v.has_variables(),
+            _ => false
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        *self == Type::Integer || *self == Type::Float
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            Type::Integer => write!(fmt, "Integer"),
+            Type::Float => write!(fmt, "Float"),
+            Type::Boolean => write!(fmt, "Boolean"),
+            Type::String => write!(fmt, "String"),
+            Type::Nothing => write!(fmt, "Nothing"),
+            Type::Instance(_, ref name) => write!(fmt, "{}", name),
+            Type::Class(ref t) => write!(fmt, "{} class", t),
+            Type::Variable(ref name) => write!(fmt, "{}", name),
+            Type::Array(ref t) if **t == Type::any() => write!(fmt, "Array"),
+            Type::Array(ref t) => write!(fmt, "{}[]", t),
+            Type::Map(ref k, _) if **k == Type::any() => write!(fmt, "Map"),
+            Type::Map(ref k, ref v) => write!(fmt, "Map({}, {})", k, v),
+            Type::Set(ref t) if **t == Type::any() => write!(fmt, "Set"),
+            Type::Set(ref t) => write!(fmt, "Set({})", t),
+        }
+    }
+}
+
+// Reads a type name made by the parser: a name, an array of a type (Integer[]), or a collection of
+// types (Map(String, Integer)). The names in it are resolved by `named`.
+pub fn structured_type<E, F: FnMut(&str) -> Result<Type, E>>(name: &str, named: &mut F) -> Result<Type, E> {
+    if let Some(element) = name.strip_suffix("[]") {
+        return Ok(Type::Array(Box::new(structured_type(element, named)?)))
+    }
+    let open = match name.find('(') {
+        Some(open) if name.ends_with(')') => open,
+        _ => return named(name)
+    };
+    let mut params = vec![];
+    let (mut depth, mut start) = (0, open + 1);
+    for (i, c) in name.char_indices().skip(open + 1) {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ',' | ')' if depth == 0 => {
+                params.push(structured_type(name[start..i].trim(), named)?);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    match (&name[..open], params.len()) {
+        ("Map", 2) => Ok(Type::Map(Box::new(params.remove(0)), Box::new(params.remove(0)))),
+        ("Set", 1) => Ok(Type::Set(Box::new(params.remove(0)))),
+        _ => named(name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeErrorKind {
+    Mismatch(String, Type, Type), // In (0), (1) was expected but (2) was found
+    UnknownVariable(String),
+    Unresolved(ResolveErrorKind),
+    DoesNotUnderstand(Type, String), // No message (1) for the receiver (0)
+    NoReceiver(String), // Shorthand send of (0) outside of a message
+    InvalidOperands(TokenType, Type, Type),
+    InvalidOperand(TokenType, Type),
+    NotInstantiable(Type),
+    UnknownMember(String, String), // Struct (0) has no member (1)
+    MissingMember(String, String), // Instance of (0) does not set member (1)
+    CannotInfer(String, String), // Generic type (0) of [(1)] is not bound by any argument
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeError {
+    pub file: String,
+    pub line: i32,
+    pub kind: TypeErrorKind,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}:{}: ", self.file, self.line)?;
+        match self.kind {
+            TypeErrorKind::Mismatch(ref what, ref expected, ref found) =>
+                write!(fmt, "Type mismatch in {}: expected {}, found {}", what, expected, found),
+            TypeErrorKind::UnknownVariable(ref name) => write!(fmt, "Unknown variable {}", name),
+            TypeErrorKind::Unresolved(ref kind) => match *kind {
+                ResolveErrorKind::UnknownStruct(ref name) => write!(fmt, "Unknown struct {}", name),
+                ResolveErrorKind::NotExported(ref m, ref name) => write!(fmt, "Struct {} is private to module {}", name, m),
+                ResolveErrorKind::Ambiguous(ref name, ref ms) => write!(fmt, "Ambiguous name {}, imported from {}", name, ms.join(", ")),
+                ref other => write!(fmt, "{:?}", other),
+            },
+            TypeErrorKind::DoesNotUnderstand(ref t, ref sel) => write!(fmt, "{} does not understand [{}]", t, sel),
+            TypeErrorKind::NoReceiver(ref sel) => write!(fmt, "[{}] has no receiver outside of a message", sel),
+            TypeErrorKind::InvalidOperands(op, ref l, ref r) => write!(fmt, "Cannot apply {:?} to {} and {}", op, l, r),
+            TypeErrorKind::InvalidOperand(op, ref t) => write!(fmt, "Cannot apply {:?} to {}", op, t),
+            TypeErrorKind::NotInstantiable(ref t) => write!(fmt, "{} is not a struct, and cannot be instanced", t),
+            TypeErrorKind::UnknownMember(ref s, ref m) => write!(fmt, "{} has no member {}", s, m),
+            TypeErrorKind::MissingMember(ref s, ref m) => write!(fmt, "Instance of {} does not set member {}", s, m),
+            TypeErrorKind::CannotInfer(ref t, ref sel) => write!(fmt, "Cannot infer type {} of [{}] from its arguments", t, sel),
+        }
+    }
+}
+
+// The types found for a module: the type of every let binding, by its id
+#[derive(Clone, Debug, Default)]
+pub struct TypeTable {
+    lets: HashMap<usize, Type>,
+}
+
+impl TypeTable {
+    pub fn get_let(&self, id: usize) -> Option<Type> {
+        self.lets.get(&id).cloned()
+    }
+}
+
+// The signature of a message declaration, with its types resolved in the module that declares it.
+// Generic messages use Type::Variable for their type names.
+struct MessageSignature {
+    receiver: Type, // The struct the message is declared for
+    args: Vec<Type>, // In keyword order
+    ret: Type,
+}
+
+// The state of checking one module
+struct Context<'m> {
+    module: &'m Module,
+    scopes: Vec<HashMap<String, Type>>,
+    ret: Option<Type>, // Declared return type of the message being checked
+    table: TypeTable,
+    errors: Vec<TypeError>,
+}
+
+impl<'m> Context<'m> {
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes.iter().rev().filter_map(|s| s.get(name)).next().cloned()
+    }
+
+    fn bind(&mut self, name: &str, t: Type) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), t);
+    }
+
+    fn error(&mut self, line: i32, kind: TypeErrorKind) {
+        self.errors.push(TypeError { file: self.module.get_file_name(), line, kind });
+    }
+}
+
+pub struct TypeChecker<'a> {
+    dispatcher: Dispatcher<'a>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(mman: &'a ModuleManager) -> TypeChecker<'a> {
+        TypeChecker { dispatcher: Dispatcher::new(mman) }
+    }
+
+    pub fn check_module(&self, m: &Module) -> Vec<TypeError> {
+        self.infer_module(m).1
+    }
+
+    // Checks a module, and records the type of every let binding, annotated or not
+    pub fn infer_module(&self, m: &Module) -> (TypeTable, Vec<TypeError>) {
+        let mut ctx = Context { module: m, scopes: vec![HashMap::new()], ret: None, table: TypeTable::default(), errors: vec![] };
+        for inst in m.get_code().iter() {
+            match **inst {
+                Expression::MessageDeclaration { ref bound_struct, is_call, ref type_params, ref args_or_name, ref body, ref ret_value, line, .. } => {
+                    let mut scope = HashMap::new();
+                    match self.declared_type(m, bound_struct, type_params, line) {
+                        Ok(t) => { scope.insert("this".to_string(), if is_call { Type::Class(Box::new(t)) } else { t }); },
+                        Err(kind) => ctx.error(line, kind)
+                    }
+                    if let Ok(ref args) = *args_or_name {
+                        for (name, tname) in args.iter() {
+                            match self.declared_type(m, tname, type_params, line) {
+                                Ok(t) => { scope.insert(name.clone(), t); },
+                                Err(kind) => ctx.error(line, kind)
+                            }
+                        }
+                    }
+                    let ret = match *ret_value {
+                        Some(ref tname) => self.declared_type(m, tname, type_params, line),
+                        None => Ok(Type::Nothing)
+                    };
+                    // Without a known return type, returns are not checked
+                    let module_scopes = ::std::mem::replace(&mut ctx.scopes, vec![scope]);
+                    ctx.ret = ret.ok();
+                    for stmt in body.iter() {
+                        self.check_statement(&mut ctx, stmt);
+                    }
+                    ctx.ret = None;
+                    ctx.scopes = module_scopes;
+                },
+                Expression::MainDeclaration { ref body, .. } => {
+                    // main sees the lets of its module, and returns nothing in particular
+                    ctx.scopes.push(HashMap::new());
+                    for stmt in body.iter() {
+                        self.check_statement(&mut ctx, stmt);
+                    }
+                    ctx.scopes.pop();
+                },
+                Expression::LetStatement { .. } => self.check_statement(&mut ctx, inst),
+                _ => {}
+            }
+        }
+        (ctx.table, ctx.errors)
+    }
+
+    // Checks statements run with bindings around them, like the session of a REPL.
+    // Gives the type of the last statement when it is an expression.
+    pub fn check_statements(&self, m: &Module, bindings: HashMap<String, Type>, code: &[Box<Expression>]) -> (Option<Type>, Vec<TypeError>) {
+        let mut ctx = Context { module: m, scopes: vec![bindings], ret: None, table: TypeTable::default(), errors: vec![] };
+        let mut last = None;
+        for stmt in code.iter() {
+            last = match **stmt {
+                Expression::LetStatement { .. } | Expression::ReturnStatement(..) | Expression::IfStatement { .. } => {
+                    self.check_statement(&mut ctx, stmt);
+                    None
+                },
+                ref expression => self.type_of(&mut ctx, expression)
+            };
+        }
+        (last, ctx.errors)
+    }
+
+    fn check_statement(&self, ctx: &mut Context, stmt: &Expression) {
+        match *stmt {
+            Expression::LetStatement { id, ref bound_name, ref ntype, ref expression, line } => {
+                let found = self.type_of(ctx, expression);
+                let expected = match *ntype {
+                    Some(ref tname) => match self.named_type(ctx.module, tname, line) {
+                        Ok(t) => Some(t),
+                        Err(kind) => { ctx.error(line, kind); None }
+                    },
+                    None => None
+                };
+                if let (Some(ref e), Some(ref f)) = (expected.clone(), found.clone()) {
+                    if !self.dispatcher.is_subtype(f, e) {
+                        ctx.error(line, TypeErrorKind::Mismatch(format!("let {}", bound_name), e.clone(), f.clone()));
+                    }
+                }
+                if let Some(t) = expected.or(found) {
+                    ctx.table.lets.insert(id, t.clone());
+                    ctx.bind(bound_name, t);
+                }
+            },
+            Expression::ReturnStatement(line, ref expression) => {
+                let found = self.type_of(ctx, expression);
+                match (ctx.ret.clone(), found) {
+                    (Some(ref e), Some(ref f)) if !self.dispatcher.is_subtype(f, e) =>
+                        ctx.error(line, TypeErrorKind::Mismatch("return".to_string(), e.clone(), f.clone())),
+                    _ => {}
+                }
+            },
+            Expression::IfStatement { ref condition, ref body, ref else_body, line } => {
+                if let Some(t) = self.type_of(ctx, condition) {
+                    if t != Type::Boolean {
+                        ctx.error(line, TypeErrorKind::Mismatch("if condition".to_string(), Type::Boolean, t));
+                    }
+                }
+                for block in Some(body).into_iter().chain(else_body.as_ref()) {
+                    ctx.scopes.push(HashMap::new());
+                    for stmt in block.iter() {
+                        self.check_statement(ctx, stmt);
+                    }
+                    ctx.scopes.pop();
+                }
+            },
+            ref expression => { self.type_of(ctx, expression); }
+        }
+    }
+
+    // The type of an expression. Errors are added to the context, and give None, so that
+    // an error is not reported again by every expression around it.
+    fn type_of(&self, ctx: &mut Context, expr: &Expression) -> Option<Type> {
+        match *expr {
+            Expression::IntegerExpression(_) => Some(Type::Integer),
+            Expression::FloatExpression(_) => Some(Type::Float),
+            Expression::StringExpression(_) => Some(Type::String),
+            Expression::BooleanExpression(_) => Some(Type::Boolean),
+            Expression::ArrayExpression(line, ref elements) => {
+                // The array is of the type of its first element, and the others must fit it
+                let types = elements.iter().map(|e| self.type_of(ctx, e)).collect::<Vec<_>>();
+                let mut types = types.into_iter().collect::<Option<Vec<_>>>()?.into_iter();
+                let first = types.next().unwrap_or(Type::Nothing);
+                for t in types {
+                    if !self.dispatcher.is_subtype(&t, &first) {
+                        ctx.error(line, TypeErrorKind::Mismatch("array element".to_string(), first.clone(), t));
+                    }
+                }
+                Some(Type::Array(Box::new(first)))
+            },
+            Expression::VariableExpression(line, ref name) => {
+                let t = ctx.lookup(name);
+                if t.is_none() {
+                    ctx.error(line, TypeErrorKind::UnknownVariable(name.clone()));
+                }
+                t
+            },
+            Expression::StructExpression(line, ref name) => match self.named_type(ctx.module, name, line) {
+                Ok(t) => Some(Type::Class(Box::new(t))),
+                Err(kind) => { ctx.error(line, kind); None }
+            },
+            Expression::PrefixExpression(line, op, ref e) => {
+                let t = self.type_of(ctx, e)?;
+                if !t.is_numeric() {
+                    ctx.error(line, TypeErrorKind::InvalidOperand(op, t));
+                    return None
+                }
+                Some(t)
+            },
+            Expression::BinaryExpression(line, op, ref l, ref r) => {
+                let lt = self.type_of(ctx, l);
+                let rt = self.type_of(ctx, r);
+                let (lt, rt) = (lt?, rt?);
+                let t = match op {
+                    TokenType::LessThan | TokenType::GreaterThan if lt.is_numeric() && rt.is_numeric() => Some(Type::Boolean),
+                    TokenType::DoubleEqual if lt == rt || (lt.is_numeric() && rt.is_numeric()) => Some(Type::Boolean),
+                    TokenType::Plus if lt == Type::String && rt == Type::String => Some(Type::String),
+                    TokenType::Plus | TokenType::Minus | TokenType::Asterisk | TokenType::Backslash
+                        if lt.is_numeric() && rt.is_numeric() => match lt == Type::Float || rt == Type::Float {
+                            true => Some(Type::Float),
+                            false => Some(Type::Integer),
+                        },
+                    _ => None
+                };
+                if t.is_none() {
+                    ctx.error(line, TypeErrorKind::InvalidOperands(op, lt, rt));
+                }
+                t
+            },
+            Expression::MemberExpression(line, ref e, ref name) => {
+                let t = self.type_of(ctx, e)?;
+                let member = self.struct_members(&t).and_then(|members| members.into_iter().find(|m| &m.0 == name));
+                match member {
+                    Some((_, Ok(mt))) => Some(mt),
+                    Some((_, Err(_))) => None, // Reported with the struct declaration
+                    None => { ctx.error(line, TypeErrorKind::UnknownMember(t.to_string(), name.clone())); None }
+                }
+            },
+            Expression::InstanceExpression { ref struct_name, ref members, line } => self.type_of_instance(ctx, struct_name, members, line),
+            Expression::MessageSend { ref receiver, ref args_or_name, line } => self.type_of_send(ctx, receiver, args_or_name, line),
+            _ => None
+        }
+    }
+
+    fn type_of_instance(&self, ctx: &mut Context, struct_name: &str, members: &[(String, Box<Expression>)], line: i32) -> Option<Type> {
+        let t = match self.named_type(ctx.module, struct_name, line) {
+            Ok(t) => t,
+            Err(kind) => { ctx.error(line, kind); return None }
+        };
+        let found = members.iter().map(|m| (m.0.clone(), self.type_of(ctx, &m.1))).collect::<Vec<_>>();
+        let declared = match self.struct_members(&t) {
+            Some(declared) => declared,
+            None => { ctx.error(line, TypeErrorKind::NotInstantiable(t)); return None }
+        };
+        for (name, ft) in found {
+            match declared.iter().find(|d| d.0 == name) {
+                Some(&(_, Ok(ref et))) => match ft {
+                    Some(ref ft) if !self.dispatcher.is_subtype(ft, et) => ctx.error(line, TypeErrorKind::Mismatch(format!("member {} of {}", name, t), et.clone(), ft.clone())),
+                    _ => {}
+                },
+                Some(&(_, Err(_))) => {}, // Reported with the struct declaration
+                None => ctx.error(line, TypeErrorKind::UnknownMember(struct_name.to_string(), name))
+            }
+        }
+        for d in declared.iter() {
+            if !members.iter().any(|m| m.0 == d.0) {
+                ctx.error(line, TypeErrorKind::MissingMember(struct_name.to_string(), d.0.clone()));
+            }
+        }
+        Some(t)
+    }
+
+    fn type_of_send(&self, ctx: &mut Context, receiver: &Option<Box<Expression>>, args_or_name: &Result<Vec<(String, Box<Expression>)>, String>, line: i32) -> Option<Type> {
+        let rt = match *receiver {
+            Some(ref e) => self.type_of(ctx, e),
+            None => {
+                let sel = selector_name(args_or_name);
+                let t = ctx.lookup("this").or_else(|| self.dispatcher.implicit_receiver(&sel));
+                if t.is_none() {
+                    ctx.error(line, TypeErrorKind::NoReceiver(sel));
+                }
+                t
+            }
+        };
+        let args = match *args_or_name {
+            Ok(ref args) => args.iter().map(|a| (a.0.clone(), self.type_of(ctx, &a.1))).collect(),
+            Err(_) => vec![]
+        };
+        let rt = rt?;
+        let sel = selector_name(args_or_name);
+        let sig = match self.dispatcher.resolve(&rt, &sel) {
+            Ok(d) => self.signature(&d)?,
+            Err(DispatchError::DoesNotUnderstand(rt, sel)) => { ctx.error(line, TypeErrorKind::DoesNotUnderstand(rt, sel)); return None }
+        };
+        let mut bindings = HashMap::new();
+        // Messages of every collection (message(T) T[] [...]) get their types from the receiver first
+        if sig.receiver.has_variables() {
+            unify(&self.dispatcher, &sig.receiver, &rt, &mut bindings);
+        }
+        // Same selector, so the keywords of the send are those of the declaration, in the same order
+        for ((name, at), et) in args.into_iter().zip(sig.args.iter()) {
+            if let Some(ref at) = at {
+                if !unify(&self.dispatcher, et, at, &mut bindings) {
+                    let et = substitute(et, &bindings).unwrap_or(et.clone());
+                    ctx.error(line, TypeErrorKind::Mismatch(format!("argument {}: of [{}]", name, sel), et, at.clone()));
+                }
+            }
+        }
+        match substitute(&sig.ret, &bindings) {
+            Ok(t) => Some(t),
+            Err(name) => { ctx.error(line, TypeErrorKind::CannotInfer(name, sel)); None }
+        }
+    }
+
+    // Resolves a type name as seen from the module m
+    fn named_type(&self, m: &Module, name: &str, line: i32) -> Result<Type, TypeErrorKind> {
+        self.dispatcher.named_type(m, name, line).map_err(TypeErrorKind::Unresolved)
+    }
+
+    // Resolves a type name of a message declaration, where the generic type names stand for themselves
+    fn declared_type(&self, m: &Module, name: &str, type_params: &[String], line: i32) -> Result<Type, TypeErrorKind> {
+        self.dispatcher.declared_type(m, name, type_params, line).map_err(TypeErrorKind::Unresolved)
+    }
+
+    // The members of a struct, with those it gets from its composers and parents
+    fn struct_members(&self, t: &Type) -> Option<Vec<(String, Result<Type, TypeErrorKind>)>> {
+        let members = self.dispatcher.members(t)?;
+        Some(members.into_iter().map(|(name, mt)| (name, mt.map_err(TypeErrorKind::Unresolved))).collect())
+    }
+
+    // The argument and return types of the message a send dispatches to
+    fn signature(&self, d: &Dispatch) -> Option<MessageSignature> {
+        let (m, decl) = self.dispatcher.declaration(d)?;
+        if let Expression::MessageDeclaration { ref bound_struct, is_call, ref type_params, ref args_or_name, ref ret_value, line, .. } = *decl {
+            let receiver = self.declared_type(m, bound_struct, type_params, line).ok()?;
+            let receiver = if is_call { Type::Class(Box::new(receiver)) } else { receiver };
+            let mut args = vec![];
+            if let Ok(ref decl_args) = *args_or_name {
+                for arg in decl_args.iter() {
+                    // Argument types that do not resolve are reported with the declaration
+                    args.push(self.declared_type(m, &arg.1, type_params, line).ok()?);
+                }
+            }
+            let ret = match *ret_value {
+                Some(ref tname) => self.declared_type(m, tname, type_params, line).ok()?,
+                None => Type::Nothing
+            };
+            return Some(MessageSignature { receiver, args, ret })
+        }
+        None
+    }
+}
+
+// Matches the declared type of an argument against the type sent. Generic type names are bound
+// to the type found at their first use, and every later use must match that type. Other types
+// match the type sent or one of its parents.
+fn unify(dispatcher: &Dispatcher, declared: &Type, found: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+    match (declared, found) {
+        (Type::Variable(name), _) if name.is_empty() => true, // Any type, as in Array for T[]
+        (Type::Variable(name), _) => match bindings.get(name).cloned() {
+            Some(bound) => bound == *found,
+            None => {
+                bindings.insert(name.clone(), found.clone());
+                true
+            }
+        },
+        (Type::Class(d), Type::Class(f)) => unify(dispatcher, d, f, bindings),
+        (Type::Array(d), Type::Array(f)) | (Type::Set(d), Type::Set(f)) => unify_element(dispatcher, d, f, bindings),
+        (Type::Map(dk, dv), Type::Map(fk, fv)) => unify_element(dispatcher, dk, fk, bindings) && unify_element(dispatcher, dv, fv, bindings),
+        _ => dispatcher.is_subtype(found, declared)
+    }
+}
+
+// The elements of an empty collection (Nothing), or of one of any type, bind nothing
+fn unify_element(dispatcher: &Dispatcher, declared: &Type, found: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+    *found == Type::Nothing || *found == Type::any() || unify(dispatcher, declared, found, bindings)
+}
+
+// Replaces the generic type names in a type by what they are bound to, or gives the first unbound name
+fn substitute(t: &Type, bindings: &HashMap<String, Type>) -> Result<Type, String> {
+    match *t {
+        Type::Variable(ref name) if name.is_empty() => Ok(t.clone()),
+        Type::Variable(ref name) => bindings.get(name).cloned().ok_or(name.clone()),
+        Type::Class(ref c) => Ok(Type::Class(Box::new(substitute(c, bindings)?))),
+        Type::Array(ref e) => Ok(Type::Array(Box::new(substitute(e, bindings)?))),
+        Type::Set(ref e) => Ok(Type::Set(Box::new(substitute(e, bindings)?))),
+        Type::Map(ref k, ref v) => Ok(Type::Map(Box::new(substitute(k, bindings)?), Box::new(substitute(v, bindings)?))),
+        ref t => Ok(t.clone())
+    }
+}
@@ -0,0 +1,133 @@
+// The natives of Std.Collection. Arrays, maps and sets never change: the messages that add to them
+// give a new collection. Keys and elements are compared by value.
+use std::rc::Rc;
+use super::super::compiler::typecheck::Type;
+use super::native::{Engine, Natives};
+use super::{RuntimeErrorKind, Value};
+
+pub fn register(natives: &mut Natives) {
+    let array = Type::Array(Box::new(Type::any()));
+    natives.register(array.clone(), false, "length", |_, r, _| Ok(Value::Integer(elements(r)?.len() as i64)));
+    natives.register(array.clone(), false, "isEmpty", |_, r, _| Ok(Value::Boolean(elements(r)?.is_empty())));
+    natives.register(array.clone(), false, "at:", |_, r, a| {
+        let (elements, i) = (elements(r)?, integer(&a[0])?);
+        match elements.get(i as usize) {
+            Some(e) if i >= 0 => Ok(e.clone()),
+            _ => Err(RuntimeErrorKind::OutOfRange(i, elements.len()))
+        }
+    });
+    natives.register(array.clone(), false, "with:", |_, r, a| {
+        let mut elements = elements(r)?.to_vec();
+        elements.push(a[0].clone());
+        Ok(Value::Array(Rc::new(elements)))
+    });
+    natives.register(array.clone(), false, "concat:", |_, r, a| {
+        let mut elements = elements(r)?.to_vec();
+        elements.extend(self::elements(&a[0])?.iter().cloned());
+        Ok(Value::Array(Rc::new(elements)))
+    });
+    natives.register(array.clone(), false, "contains:", |_, r, a| Ok(Value::Boolean(elements(r)?.contains(&a[0]))));
+    natives.register(array.clone(), false, "each:", |rt, r, a| each(rt, elements(r)?, &a[0]));
+    natives.register(array.clone(), false, "map:", |rt, r, a| {
+        let mut results = vec![];
+        for e in elements(r)?.iter() {
+            results.push(rt.send(a[0].clone(), "value:", vec![e.clone()])?);
+        }
+        Ok(Value::Array(Rc::new(results)))
+    });
+    natives.register(array, false, "toSet", |_, r, _| Ok(Value::Set(Rc::new(distinct(elements(r)?)))));
+
+    let map = Type::Map(Box::new(Type::any()), Box::new(Type::any()));
+    natives.register(map.clone(), true, "keys:values:", |_, _, a| {
+        let (keys, values) = (elements(&a[0])?, elements(&a[1])?);
+        if keys.len() != values.len() {
+            // The first key without a value, or the first value without a key
+            return Err(RuntimeErrorKind::OutOfRange(keys.len().min(values.len()) as i64, keys.len().min(values.len())))
+        }
+        let mut pairs = vec![];
+        for (k, v) in keys.iter().zip(values.iter()) {
+            put(&mut pairs, k, v);
+        }
+        Ok(Value::Map(Rc::new(pairs)))
+    });
+    natives.register(map.clone(), false, "size", |_, r, _| Ok(Value::Integer(pairs(r)?.len() as i64)));
+    natives.register(map.clone(), false, "at:", |_, r, a| match pairs(r)?.iter().find(|p| p.0 == a[0]) {
+        Some(p) => Ok(p.1.clone()),
+        None => Err(RuntimeErrorKind::MissingKey(a[0].clone()))
+    });
+    natives.register(map.clone(), false, "at:put:", |_, r, a| {
+        let mut pairs = pairs(r)?.to_vec();
+        put(&mut pairs, &a[0], &a[1]);
+        Ok(Value::Map(Rc::new(pairs)))
+    });
+    natives.register(map.clone(), false, "containsKey:", |_, r, a| Ok(Value::Boolean(pairs(r)?.iter().any(|p| p.0 == a[0]))));
+    natives.register(map.clone(), false, "keys", |_, r, _| Ok(Value::Array(Rc::new(pairs(r)?.iter().map(|p| p.0.clone()).collect()))));
+    natives.register(map.clone(), false, "values", |_, r, _| Ok(Value::Array(Rc::new(pairs(r)?.iter().map(|p| p.1.clone()).collect()))));
+    natives.register(map, false, "each:", |rt, r, a| {
+        for p in pairs(r)?.iter() {
+            rt.send(a[0].clone(), "key:value:", vec![p.0.clone(), p.1.clone()])?;
+        }
+        Ok(Value::Nothing)
+    });
+
+    let set = Type::Set(Box::new(Type::any()));
+    natives.register(set.clone(), false, "size", |_, r, _| Ok(Value::Integer(elements(r)?.len() as i64)));
+    natives.register(set.clone(), false, "contains:", |_, r, a| Ok(Value::Boolean(elements(r)?.contains(&a[0]))));
+    natives.register(set.clone(), false, "with:", |_, r, a| {
+        let mut elements = elements(r)?.to_vec();
+        if !elements.contains(&a[0]) {
+            elements.push(a[0].clone());
+        }
+        Ok(Value::Set(Rc::new(elements)))
+    });
+    natives.register(set.clone(), false, "toArray", |_, r, _| Ok(Value::Array(Rc::new(elements(r)?.to_vec()))));
+    natives.register(set, false, "each:", |rt, r, a| each(rt, elements(r)?, &a[0]));
+}
+
+// Sends value: with each element to the argument of each:
+fn each(rt: &mut dyn Engine, elements: &[Value], f: &Value) -> Result<Value, RuntimeErrorKind> {
+    for e in elements.iter() {
+        rt.send(f.clone(), "value:", vec![e.clone()])?;
+    }
+    Ok(Value::Nothing)
+}
+
+// The elements of an array or a set
+fn elements(v: &Value) -> Result<&[Value], RuntimeErrorKind> {
+    match *v {
+        Value::Array(ref a) | Value::Set(ref a) => Ok(a),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::Array(Box::new(Type::any())), v.get_type()))
+    }
+}
+
+fn pairs(v: &Value) -> Result<&[(Value, Value)], RuntimeErrorKind> {
+    match *v {
+        Value::Map(ref m) => Ok(m),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::Map(Box::new(Type::any()), Box::new(Type::any())), v.get_type()))
+    }
+}
+
+fn integer(v: &Value) -> Result<i64, RuntimeErrorKind> {
+    match *v {
+        Value::Integer(i) => Ok(i),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::Integer, v.get_type()))
+    }
+}
+
+fn distinct(elements: &[Value]) -> Vec<Value> {
+    let mut found: Vec<Value> = vec![];
+    for e in elements.iter() {
+        if !found.contains(e) {
+            found.push(e.clone());
+        }
+    }
+    found
+}
+
+// Sets the value of a key, keeping its place if it is already there
+fn put(pairs: &mut Vec<(Value, Value)>, k: &Value, v: &Value) {
+    match pairs.iter_mut().find(|p| p.0 == *k) {
+        Some(p) => p.1 = v.clone(),
+        None => pairs.push((k.clone(), v.clone()))
+    }
+}
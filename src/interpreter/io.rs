@@ -0,0 +1,138 @@
+// The natives of Std.IO. They go through the Io of the context, which is the standard input and output
+// and the file system, unless the program is run with another one (like a memory Io in tests).
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use super::super::compiler::typecheck::Type;
+use super::native::{Engine, Natives};
+use super::{Instance, RuntimeErrorKind, Value};
+
+pub trait Io {
+    fn print(&mut self, text: &str) -> io::Result<()>;
+    // The next line of the input, without its end of line. None at the end of the input.
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+    // Opens a file to read it and to write at its end. It is created if it does not exist.
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn Stream>>;
+}
+
+pub trait Stream {
+    // The rest of the file
+    fn read(&mut self) -> io::Result<String>;
+    fn write(&mut self, text: &str) -> io::Result<()>;
+}
+
+// The standard input and output, and the file system
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(text.as_bytes())?;
+        out.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None)
+        }
+        Ok(Some(line.trim_end_matches(&['\n', '\r'][..]).to_string()))
+    }
+
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn Stream>> {
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        Ok(Box::new(FileStream { file, at: 0 }))
+    }
+}
+
+// Writing moves to the end of the file, so reading keeps its own place
+struct FileStream {
+    file: File,
+    at: u64,
+}
+
+impl Stream for FileStream {
+    fn read(&mut self) -> io::Result<String> {
+        let mut text = String::new();
+        self.file.seek(SeekFrom::Start(self.at))?;
+        self.file.read_to_string(&mut text)?;
+        self.at += text.len() as u64;
+        Ok(text)
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.file.write_all(text.as_bytes())
+    }
+}
+
+pub fn register(natives: &mut Natives) {
+    let io = Type::Instance("Std.IO".to_string(), "IO".to_string());
+    natives.register(io.clone(), true, "print:", |rt, _, a| print(rt, a[0].to_string()));
+    natives.register(io.clone(), true, "println:", |rt, _, a| print(rt, a[0].to_string() + "\n"));
+    natives.register(io.clone(), true, "readLine", |rt, _, _| {
+        let line = rt.context().io.read_line().map_err(failed)?;
+        Ok(Value::String(line.unwrap_or_default()))
+    });
+    natives.register(io, true, "open:", |rt, _, a| {
+        let path = match a[0] {
+            Value::String(ref s) => s.clone(),
+            ref v => return Err(RuntimeErrorKind::InvalidArgument(Type::String, v.get_type()))
+        };
+        let context = rt.context();
+        let stream = context.io.open(&path).map_err(failed)?;
+        context.files.push(Some(stream));
+        let mut members = BTreeMap::new();
+        members.insert("handle".to_string(), Value::Integer(context.files.len() as i64 - 1));
+        Ok(Value::Instance(Rc::new(Instance { struct_type: file_type(), members })))
+    });
+
+    natives.register(file_type(), false, "read", |rt, r, _| {
+        let text = stream(rt, r)?.read().map_err(failed)?;
+        Ok(Value::String(text))
+    });
+    natives.register(file_type(), false, "write:", |rt, r, a| {
+        stream(rt, r)?.write(&a[0].to_string()).map_err(failed)?;
+        Ok(Value::Nothing)
+    });
+    natives.register(file_type(), false, "close", |rt, r, _| {
+        let handle = handle(r)?;
+        match rt.context().files.get_mut(handle).and_then(|f| f.take()) {
+            Some(_) => Ok(Value::Nothing),
+            None => Err(RuntimeErrorKind::Io("The file is closed".to_string()))
+        }
+    });
+}
+
+fn file_type() -> Type {
+    Type::Instance("Std.IO".to_string(), "File".to_string())
+}
+
+fn print(rt: &mut dyn Engine, text: String) -> Result<Value, RuntimeErrorKind> {
+    rt.context().io.print(&text).map_err(failed)?;
+    Ok(Value::Nothing)
+}
+
+fn failed(e: io::Error) -> RuntimeErrorKind {
+    RuntimeErrorKind::Io(e.to_string())
+}
+
+fn handle(file: &Value) -> Result<usize, RuntimeErrorKind> {
+    match *file {
+        Value::Instance(ref i) => match i.members.get("handle") {
+            Some(&Value::Integer(h)) if h >= 0 => Ok(h as usize),
+            _ => Err(RuntimeErrorKind::Io("Not an open file".to_string()))
+        },
+        ref v => Err(RuntimeErrorKind::InvalidArgument(file_type(), v.get_type()))
+    }
+}
+
+// The open file of a File instance
+fn stream<'r>(rt: &'r mut dyn Engine, file: &Value) -> Result<&'r mut dyn Stream, RuntimeErrorKind> {
+    let handle = handle(file)?;
+    match rt.context().files.get_mut(handle) {
+        Some(&mut Some(ref mut stream)) => Ok(&mut **stream),
+        _ => Err(RuntimeErrorKind::Io("The file is closed".to_string()))
+    }
+}
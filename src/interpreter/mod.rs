@@ -0,0 +1,400 @@
+// Runs Kobold modules by walking their syntax tree, starting from the main block of a module
+pub mod value;
+pub mod native;
+mod number;
+mod string;
+mod collection;
+pub mod io;
+mod system;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::rc::Rc;
+use super::compiler::ast::{Expression, selector_name};
+use super::compiler::dispatch::{Dispatcher, DispatchError, DispatchTable};
+use super::compiler::module::{Module, ModuleManager};
+use super::compiler::resolver::ResolveErrorKind;
+use super::compiler::token::TokenType;
+use super::compiler::typecheck::Type;
+pub use self::value::{Instance, Value, binary, unary};
+pub use self::native::{Context, Ctx, Engine, HostNative, Natives};
+pub use self::io::Io;
+
+// How many messages can run inside each other. Deeper sends fail, instead of overflowing the stack of the
+// process, which would end a program that embeds Kobold. The interpreter needs the 8 MiB stack of a main
+// thread for that many, so other threads that run Kobold should be given as much.
+pub const MAX_DEPTH: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeErrorKind {
+    NoMain(String), // The module (0) has no main block to run
+    UnknownVariable(String),
+    Unresolved(ResolveErrorKind),
+    DoesNotUnderstand(Type, String), // No message (1) for the receiver (0)
+    NoReceiver(String), // Shorthand send of (0) outside of a message
+    NotInstantiable(Type),
+    UnknownMember(Type, String), // (0) has no member (1)
+    MissingMember(Type, String), // Instance of (0) does not set member (1)
+    InvalidOperands(TokenType, Type, Type),
+    InvalidOperand(TokenType, Type),
+    NotBoolean(Type), // The condition of an if
+    InvalidNumber(String), // A literal that does not fit its type, or a float too large for an integer
+    Overflow(TokenType),
+    DivisionByZero,
+    OutOfRange(i64, usize), // Index (0) of something of length (1)
+    InvalidArgument(Type, Type), // A native expected (0), but was given (1)
+    MissingKey(Value), // A map has no value for the key (0)
+    Io(String), // Reading or writing failed, for the reason (0)
+    Exit(i32), // The program asked to exit the process with the code (0)
+    InvalidExitCode(i64), // An exit code (0) that the process cannot have
+    Limit(String), // Too many of (0) for the bytecode of one message
+    StackOverflow, // More than MAX_DEPTH messages were running at once
+    Failed(Box<RuntimeError>), // A message sent by a native failed, where the error (0) says
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimeError {
+    pub file: String,
+    pub line: i32, // 0 when the error is about the module as a whole
+    pub kind: RuntimeErrorKind,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self.line {
+            0 => write!(fmt, "{}: ", self.file)?,
+            line => write!(fmt, "{}:{}: ", self.file, line)?,
+        }
+        match self.kind {
+            RuntimeErrorKind::NoMain(ref m) => write!(fmt, "Module {} has no main block", m),
+            RuntimeErrorKind::UnknownVariable(ref name) => write!(fmt, "Unknown variable {}", name),
+            RuntimeErrorKind::Unresolved(ref kind) => write!(fmt, "Could not resolve {:?}", kind),
+            RuntimeErrorKind::DoesNotUnderstand(ref t, ref sel) => write!(fmt, "{} does not understand [{}]", t, sel),
+            RuntimeErrorKind::NoReceiver(ref sel) => write!(fmt, "[{}] has no receiver outside of a message", sel),
+            RuntimeErrorKind::NotInstantiable(ref t) => write!(fmt, "{} is not a struct, and cannot be instanced", t),
+            RuntimeErrorKind::UnknownMember(ref t, ref m) => write!(fmt, "{} has no member {}", t, m),
+            RuntimeErrorKind::MissingMember(ref t, ref m) => write!(fmt, "Instance of {} does not set member {}", t, m),
+            RuntimeErrorKind::InvalidOperands(op, ref l, ref r) => write!(fmt, "Cannot apply {:?} to {} and {}", op, l, r),
+            RuntimeErrorKind::InvalidOperand(op, ref t) => write!(fmt, "Cannot apply {:?} to {}", op, t),
+            RuntimeErrorKind::NotBoolean(ref t) => write!(fmt, "Condition of if must be Boolean, found {}", t),
+            RuntimeErrorKind::InvalidNumber(ref s) => write!(fmt, "Invalid number {}", s),
+            RuntimeErrorKind::Overflow(op) => write!(fmt, "Integer overflow in {:?}", op),
+            RuntimeErrorKind::DivisionByZero => write!(fmt, "Division by zero"),
+            RuntimeErrorKind::OutOfRange(i, length) => write!(fmt, "Index {} is out of range for length {}", i, length),
+            RuntimeErrorKind::InvalidArgument(ref e, ref f) => write!(fmt, "Expected an argument of type {}, found {}", e, f),
+            RuntimeErrorKind::MissingKey(ref k) => write!(fmt, "No value for the key {} in the map", k),
+            RuntimeErrorKind::Io(ref reason) => write!(fmt, "IO error: {}", reason),
+            RuntimeErrorKind::Exit(code) => write!(fmt, "Exited with code {}", code),
+            RuntimeErrorKind::InvalidExitCode(code) => write!(fmt, "Exit code {} is out of range", code),
+            RuntimeErrorKind::Limit(ref what) => write!(fmt, "Too many {} in one message", what),
+            RuntimeErrorKind::StackOverflow => write!(fmt, "Stack overflow, more than {} messages deep", MAX_DEPTH),
+            RuntimeErrorKind::Failed(ref e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+// The state of one message (or main block) being run
+struct Frame<'a> {
+    module: &'a Module, // Names in the code are resolved from here
+    scopes: Vec<HashMap<String, Value>>,
+    line: i32, // The last line seen, for errors in expressions that do not keep their line
+}
+
+impl<'a> Frame<'a> {
+    fn lookup(&self, name: &str) -> Option<Value> {
+        self.scopes.iter().rev().filter_map(|s| s.get(name)).next().cloned()
+    }
+
+    fn bind(&mut self, name: &str, v: Value) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), v);
+    }
+
+    // Errors are boxed, to keep the results of the evaluator small. The errors of messages sent by
+    // natives keep where they happened.
+    fn error(&self, kind: RuntimeErrorKind) -> Box<RuntimeError> {
+        match kind {
+            RuntimeErrorKind::Failed(e) => e,
+            kind => Box::new(RuntimeError { file: self.module.get_file_name(), line: self.line, kind })
+        }
+    }
+}
+
+// What to do after a statement
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+pub struct Interpreter<'a> {
+    dispatcher: Dispatcher<'a>,
+    natives: Rc<Natives>,
+    context: Context,
+    depth: usize, // The messages running
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(mman: &'a ModuleManager) -> Interpreter<'a> {
+        Interpreter::sharing(mman, Dispatcher::new(mman).table(), Rc::new(Natives::new()))
+    }
+
+    // Runs with the table of a dispatcher over the same modules, and with natives that already have those
+    // of the std modules. Nothing is indexed again, for a program that embeds Kobold and sends many messages.
+    pub fn sharing(mman: &'a ModuleManager, table: Rc<DispatchTable>, natives: Rc<Natives>) -> Interpreter<'a> {
+        Interpreter { dispatcher: Dispatcher::with_table(mman, table), natives, context: Context::default(), depth: 0 }
+    }
+
+    // Runs with another Io than the standard input and output
+    pub fn with_io(mut self, io: Box<dyn Io>) -> Interpreter<'a> {
+        self.context.io = io;
+        self
+    }
+
+    // The arguments that Std.System gives to the program
+    pub fn with_args(mut self, args: Vec<String>) -> Interpreter<'a> {
+        self.context.launch.args = args;
+        self
+    }
+
+    // Sends a message from outside of any module, like a program that embeds Kobold does
+    pub fn send_message(&mut self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, Box<RuntimeError>> {
+        self.invoke(receiver, selector, args).map_err(|kind| match kind {
+            RuntimeErrorKind::Failed(e) => e,
+            kind => Box::new(RuntimeError { file: "<host>".to_string(), line: 0, kind })
+        })
+    }
+
+    // Runs the lets of a module, then its main block. Gives what main returns, or Nothing.
+    pub fn run_main(&mut self, m: &'a Module) -> Result<Value, Box<RuntimeError>> {
+        let mut frame = Frame { module: m, scopes: vec![HashMap::new()], line: 0 };
+        self.context.launch.script = m.is_script();
+        let main = m.get_code().iter().filter_map(|inst| match **inst {
+            Expression::MainDeclaration { ref body, .. } => Some(body),
+            _ => None
+        }).next();
+        let main = match main {
+            Some(body) => body,
+            None => return Err(frame.error(RuntimeErrorKind::NoMain(m.get_full_name())))
+        };
+        for inst in m.get_code().iter() {
+            if let Expression::LetStatement { .. } = **inst {
+                self.exec(&mut frame, inst)?;
+            }
+        }
+        match self.exec_block(&mut frame, main)? {
+            Flow::Return(v) => Ok(v),
+            Flow::Next => Ok(Value::Nothing)
+        }
+    }
+
+    // Runs statements with bindings that outlive them, like the session of a REPL. Gives what they
+    // return, or the value of the last statement when it is an expression. New lets are kept in the
+    // bindings, even when a later statement fails.
+    pub fn run_statements(&mut self, m: &'a Module, bindings: &mut HashMap<String, Value>, code: &'a [Box<Expression>]) -> Result<Value, Box<RuntimeError>> {
+        let mut frame = Frame { module: m, scopes: vec![::std::mem::take(bindings)], line: 0 };
+        self.context.launch.script = m.is_script();
+        let mut result = Ok(Value::Nothing);
+        for stmt in code.iter() {
+            result = match **stmt {
+                Expression::LetStatement { .. } | Expression::ReturnStatement(..) | Expression::IfStatement { .. } => match self.exec(&mut frame, stmt) {
+                    Ok(Flow::Next) => Ok(Value::Nothing),
+                    Ok(Flow::Return(v)) => { result = Ok(v); break },
+                    Err(e) => Err(e)
+                },
+                ref expression => self.eval(&mut frame, expression)
+            };
+            if result.is_err() {
+                break
+            }
+        }
+        *bindings = frame.scopes.pop().unwrap();
+        result
+    }
+
+    fn exec_block(&mut self, frame: &mut Frame<'a>, block: &'a [Box<Expression>]) -> Result<Flow, Box<RuntimeError>> {
+        frame.scopes.push(HashMap::new());
+        let mut flow = Flow::Next;
+        for stmt in block.iter() {
+            match self.exec(frame, stmt) {
+                Ok(Flow::Next) => {},
+                other => { flow = other?; break }
+            }
+        }
+        frame.scopes.pop();
+        Ok(flow)
+    }
+
+    fn exec(&mut self, frame: &mut Frame<'a>, stmt: &'a Expression) -> Result<Flow, Box<RuntimeError>> {
+        match *stmt {
+            Expression::LetStatement { ref bound_name, ref expression, line, .. } => {
+                frame.line = line;
+                let v = self.eval(frame, expression)?;
+                frame.bind(bound_name, v);
+            },
+            Expression::ReturnStatement(line, ref expression) => {
+                frame.line = line;
+                return Ok(Flow::Return(self.eval(frame, expression)?))
+            },
+            Expression::IfStatement { ref condition, ref body, ref else_body, line } => {
+                frame.line = line;
+                match self.eval(frame, condition)? {
+                    Value::Boolean(true) => return self.exec_block(frame, body),
+                    Value::Boolean(false) => if let Some(ref else_body) = *else_body {
+                        return self.exec_block(frame, else_body)
+                    },
+                    v => {
+                        frame.line = line;
+                        return Err(frame.error(RuntimeErrorKind::NotBoolean(v.get_type())))
+                    }
+                }
+            },
+            ref expression => { self.eval(frame, expression)?; }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn eval(&mut self, frame: &mut Frame<'a>, expr: &'a Expression) -> Result<Value, Box<RuntimeError>> {
+        match *expr {
+            Expression::IntegerExpression(ref s) => s.parse().map(Value::Integer)
+                .map_err(|_| frame.error(RuntimeErrorKind::InvalidNumber(s.clone()))),
+            Expression::FloatExpression(ref s) => s.parse().map(Value::Float)
+                .map_err(|_| frame.error(RuntimeErrorKind::InvalidNumber(s.clone()))),
+            Expression::StringExpression(ref s) => Ok(Value::String(s.clone())),
+            Expression::BooleanExpression(b) => Ok(Value::Boolean(b)),
+            Expression::ArrayExpression(line, ref elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for e in elements.iter() {
+                    values.push(self.eval(frame, e)?);
+                }
+                frame.line = line;
+                Ok(Value::Array(Rc::new(values)))
+            },
+            Expression::VariableExpression(line, ref name) => {
+                frame.line = line;
+                frame.lookup(name).ok_or_else(|| frame.error(RuntimeErrorKind::UnknownVariable(name.clone())))
+            },
+            Expression::StructExpression(line, ref name) => {
+                frame.line = line;
+                match self.dispatcher.named_type(frame.module, name, line) {
+                    Ok(t) => Ok(Value::Class(t)),
+                    Err(kind) => Err(frame.error(RuntimeErrorKind::Unresolved(kind)))
+                }
+            },
+            Expression::PrefixExpression(line, op, ref e) => {
+                let v = self.eval(frame, e)?;
+                frame.line = line;
+                unary(op, v).map_err(|kind| frame.error(kind))
+            },
+            Expression::BinaryExpression(line, op, ref l, ref r) => {
+                let l = self.eval(frame, l)?;
+                let r = self.eval(frame, r)?;
+                frame.line = line;
+                binary(op, l, r).map_err(|kind| frame.error(kind))
+            },
+            Expression::MemberExpression(line, ref e, ref name) => {
+                let v = self.eval(frame, e)?;
+                frame.line = line;
+                let member = match v {
+                    Value::Instance(ref i) => i.members.get(name).cloned(),
+                    _ => None
+                };
+                member.ok_or_else(|| frame.error(RuntimeErrorKind::UnknownMember(v.get_type(), name.clone())))
+            },
+            Expression::InstanceExpression { ref struct_name, ref members, line } => self.instance(frame, struct_name, members, line),
+            Expression::MessageSend { ref receiver, ref args_or_name, line } => self.send(frame, receiver, args_or_name, line),
+            _ => Ok(Value::Nothing)
+        }
+    }
+
+    fn instance(&mut self, frame: &mut Frame<'a>, struct_name: &str, members: &'a [(String, Box<Expression>)], line: i32) -> Result<Value, Box<RuntimeError>> {
+        frame.line = line;
+        let t = self.dispatcher.named_type(frame.module, struct_name, line)
+            .map_err(|kind| frame.error(RuntimeErrorKind::Unresolved(kind)))?;
+        let declared = self.dispatcher.members(&t)
+            .ok_or_else(|| frame.error(RuntimeErrorKind::NotInstantiable(t.clone())))?;
+        let mut values = BTreeMap::new();
+        for (name, e) in members.iter() {
+            let v = self.eval(frame, e)?;
+            values.insert(name.clone(), v);
+        }
+        frame.line = line;
+        if let Some(name) = values.keys().find(|name| !declared.iter().any(|d| &d.0 == *name)) {
+            return Err(frame.error(RuntimeErrorKind::UnknownMember(t.clone(), name.clone())))
+        }
+        if let Some(d) = declared.iter().find(|d| !values.contains_key(&d.0)) {
+            return Err(frame.error(RuntimeErrorKind::MissingMember(t.clone(), d.0.clone())))
+        }
+        Ok(Value::Instance(Rc::new(Instance { struct_type: t, members: values })))
+    }
+
+    fn send(&mut self, frame: &mut Frame<'a>, receiver: &'a Option<Box<Expression>>, args_or_name: &'a Result<Vec<(String, Box<Expression>)>, String>, line: i32) -> Result<Value, Box<RuntimeError>> {
+        frame.line = line;
+        let sel = selector_name(args_or_name);
+        let rv = match *receiver {
+            Some(ref e) => self.eval(frame, e)?,
+            None => match frame.lookup("this") {
+                Some(this) => this,
+                None => match self.dispatcher.implicit_receiver(&sel) {
+                    Some(Type::Class(t)) => Value::Class(*t),
+                    _ => return Err(frame.error(RuntimeErrorKind::NoReceiver(sel.clone())))
+                }
+            }
+        };
+        let mut args = vec![];
+        if let Ok(ref sent) = *args_or_name {
+            for a in sent.iter() {
+                args.push(self.eval(frame, &a.1)?);
+            }
+        }
+        frame.line = line;
+        self.invoke(rv, &sel, args).map_err(|kind| frame.error(kind))
+    }
+
+    // Runs the message that a receiver answers to a selector, with the arguments already evaluated.
+    // Errors in the message are Failed, with where they happened.
+    fn invoke(&mut self, rv: Value, sel: &str, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+        let d = match self.dispatcher.resolve(&rv.get_type(), sel) {
+            Ok(d) => d,
+            Err(DispatchError::DoesNotUnderstand(t, sel)) => return Err(RuntimeErrorKind::DoesNotUnderstand(t, sel))
+        };
+        if let Some(n) = self.natives.find(&d.owner, matches!(rv, Value::Class(_)), sel) {
+            return self.natives.get(n).call(self, &rv, &args)
+        }
+        let (m, decl) = match self.dispatcher.declaration(&d) {
+            Some(found) => found,
+            None => return Err(RuntimeErrorKind::DoesNotUnderstand(rv.get_type(), sel.to_string()))
+        };
+        if let Expression::MessageDeclaration { args_or_name: ref declared, ref body, line, .. } = *decl {
+            let mut scope = HashMap::new();
+            scope.insert("this".to_string(), rv);
+            if let Ok(ref declared) = *declared {
+                // Same selector, so the arguments are in the order of the declaration
+                for (a, v) in declared.iter().zip(args) {
+                    scope.insert(a.0.clone(), v);
+                }
+            }
+            if self.depth == MAX_DEPTH {
+                return Err(RuntimeErrorKind::StackOverflow)
+            }
+            let mut callee = Frame { module: m, scopes: vec![scope], line };
+            self.depth += 1;
+            let flow = self.exec_block(&mut callee, body);
+            self.depth -= 1;
+            match flow {
+                Ok(Flow::Return(v)) => return Ok(v),
+                Ok(Flow::Next) => {},
+                Err(e) => return Err(RuntimeErrorKind::Failed(e))
+            }
+        }
+        Ok(Value::Nothing)
+    }
+}
+
+impl<'a> Engine for Interpreter<'a> {
+    fn context(&mut self) -> &mut Context {
+        &mut self.context
+    }
+
+    fn send(&mut self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+        self.invoke(receiver, selector, args)
+    }
+}
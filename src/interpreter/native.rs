@@ -0,0 +1,116 @@
+// Messages implemented in Rust. They are declared in the std modules like any other message, which gives
+// their types, and the runtime runs the native instead of the body of the declaration.
+use std::collections::HashMap;
+use super::super::compiler::typecheck::Type;
+use super::io::{Io, StdIo, Stream};
+use super::{RuntimeErrorKind, Value};
+
+// How the program was started
+#[derive(Clone, Debug, Default)]
+pub struct Launch {
+    pub script: bool, // The module being run is a script (.ksc)
+    pub args: Vec<String>, // The arguments given to the program
+}
+
+// What natives can see of the running program
+pub struct Context {
+    pub launch: Launch,
+    pub io: Box<dyn Io>,
+    pub files: Vec<Option<Box<dyn Stream>>>, // The files opened by Std.IO, by handle. None once closed.
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context { launch: Launch::default(), io: Box::new(StdIo), files: vec![] }
+    }
+}
+
+// What natives can do with the engine running them
+pub trait Engine {
+    fn context(&mut self) -> &mut Context;
+    // Sends a message, like a send in the code. The errors of the message run are Failed.
+    fn send(&mut self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, RuntimeErrorKind>;
+}
+
+// A native gets the receiver, then the arguments in the order of the selector
+pub type Native = fn(&mut dyn Engine, &Value, &[Value]) -> Result<Value, RuntimeErrorKind>;
+
+// A native of a program that embeds Kobold. The receiver is in the Ctx.
+pub type HostNative = fn(&mut Ctx, &[Value]) -> Result<Value, RuntimeErrorKind>;
+
+// What a host native can see and do
+pub struct Ctx<'e> {
+    engine: &'e mut dyn Engine,
+    receiver: Value,
+}
+
+impl<'e> Ctx<'e> {
+    pub fn receiver(&self) -> &Value {
+        &self.receiver
+    }
+
+    pub fn context(&mut self) -> &mut Context {
+        self.engine.context()
+    }
+
+    pub fn send(&mut self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+        self.engine.send(receiver, selector, args)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum NativeFn {
+    Std(Native),
+    Host(HostNative),
+}
+
+impl NativeFn {
+    pub fn call(self, engine: &mut dyn Engine, receiver: &Value, args: &[Value]) -> Result<Value, RuntimeErrorKind> {
+        match self {
+            NativeFn::Std(f) => f(engine, receiver, args),
+            NativeFn::Host(f) => f(&mut Ctx { engine, receiver: receiver.clone() }, args)
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Natives {
+    list: Vec<NativeFn>,
+    index: HashMap<(Type, bool, String), usize>, // (struct, is call, selector)
+}
+
+impl Natives {
+    // The natives of the std modules
+    pub fn new() -> Natives {
+        let mut natives = Natives { list: vec![], index: HashMap::new() };
+        super::system::register(&mut natives);
+        super::number::register(&mut natives);
+        super::string::register(&mut natives);
+        super::collection::register(&mut natives);
+        super::io::register(&mut natives);
+        natives
+    }
+
+    pub fn register(&mut self, bound: Type, is_call: bool, selector: &str, f: Native) {
+        self.add(bound, is_call, selector, NativeFn::Std(f));
+    }
+
+    // A host native replaces a native of the same message
+    pub fn register_host(&mut self, bound: Type, is_call: bool, selector: &str, f: HostNative) {
+        self.add(bound, is_call, selector, NativeFn::Host(f));
+    }
+
+    fn add(&mut self, bound: Type, is_call: bool, selector: &str, f: NativeFn) {
+        self.list.push(f);
+        self.index.insert((bound, is_call, selector.to_string()), self.list.len() - 1);
+    }
+
+    // The number of the native for a message of a struct, to keep in caches
+    pub fn find(&self, bound: &Type, is_call: bool, selector: &str) -> Option<usize> {
+        self.index.get(&(bound.clone(), is_call, selector.to_string())).cloned()
+    }
+
+    pub fn get(&self, n: usize) -> NativeFn {
+        self.list[n]
+    }
+}
@@ -0,0 +1,76 @@
+// The natives of Std.Number. Integer and Float are declared there with Number as their parent.
+// The arithmetic messages do what the operators do.
+use super::super::compiler::token::TokenType;
+use super::super::compiler::typecheck::Type;
+use super::native::Natives;
+use super::{RuntimeErrorKind, Value, binary, unary};
+
+pub fn register(natives: &mut Natives) {
+    let number = Type::Instance("Std.Number".to_string(), "Number".to_string());
+    natives.register(number.clone(), false, "toFloat", |_, r, _| Ok(Value::Float(to_float(r))));
+    natives.register(number.clone(), false, "toInteger", |_, r, _| match *r {
+        Value::Float(f) => to_integer(f.trunc()),
+        ref r => Ok(r.clone())
+    });
+    natives.register(number.clone(), false, "toString", |_, r, _| Ok(Value::String(r.to_string())));
+    natives.register(number.clone(), false, "isZero", |_, r, _| Ok(Value::Boolean(to_float(r) == 0.0)));
+    natives.register(number.clone(), false, "sign", |_, r, _| {
+        let f = to_float(r);
+        Ok(Value::Integer(if f > 0.0 { 1 } else if f < 0.0 { -1 } else { 0 }))
+    });
+    natives.register(number.clone(), false, "lessThan:", |_, r, a| binary(TokenType::LessThan, r.clone(), a[0].clone()));
+    natives.register(number.clone(), false, "greaterThan:", |_, r, a| binary(TokenType::GreaterThan, r.clone(), a[0].clone()));
+    natives.register(number, false, "equals:", |_, r, a| binary(TokenType::DoubleEqual, r.clone(), a[0].clone()));
+
+    for t in &[Type::Integer, Type::Float] {
+        natives.register(t.clone(), false, "plus:", |_, r, a| binary(TokenType::Plus, r.clone(), a[0].clone()));
+        natives.register(t.clone(), false, "minus:", |_, r, a| binary(TokenType::Minus, r.clone(), a[0].clone()));
+        natives.register(t.clone(), false, "times:", |_, r, a| binary(TokenType::Asterisk, r.clone(), a[0].clone()));
+        natives.register(t.clone(), false, "dividedBy:", |_, r, a| binary(TokenType::Backslash, r.clone(), a[0].clone()));
+        natives.register(t.clone(), false, "negated", |_, r, _| unary(TokenType::Minus, r.clone()));
+        natives.register(t.clone(), false, "abs", |_, r, _| match *r {
+            Value::Integer(i) => i.checked_abs().map(Value::Integer).ok_or(RuntimeErrorKind::Overflow(TokenType::Minus)),
+            ref r => Ok(Value::Float(to_float(r).abs()))
+        });
+        natives.register(t.clone(), false, "max:", |_, r, a| pick(r, &a[0], true));
+        natives.register(t.clone(), false, "min:", |_, r, a| pick(r, &a[0], false));
+    }
+
+    natives.register(Type::Integer, false, "modulo:", |_, r, a| match (r, &a[0]) {
+        (_, &Value::Integer(0)) => Err(RuntimeErrorKind::DivisionByZero),
+        (&Value::Integer(a), &Value::Integer(b)) => Ok(Value::Integer(a.wrapping_rem(b))),
+        (r, a) => Err(RuntimeErrorKind::InvalidOperands(TokenType::Backslash, r.get_type(), a.get_type()))
+    });
+
+    natives.register(Type::Float, false, "floor", |_, r, _| to_integer(to_float(r).floor()));
+    natives.register(Type::Float, false, "ceiling", |_, r, _| to_integer(to_float(r).ceil()));
+    natives.register(Type::Float, false, "round", |_, r, _| to_integer(to_float(r).round()));
+    natives.register(Type::Float, false, "sqrt", |_, r, _| Ok(Value::Float(to_float(r).sqrt())));
+}
+
+fn to_float(v: &Value) -> f64 {
+    match *v {
+        Value::Integer(i) => i as f64,
+        Value::Float(f) => f,
+        _ => f64::NAN
+    }
+}
+
+// A whole float as an integer, if it fits
+fn to_integer(f: f64) -> Result<Value, RuntimeErrorKind> {
+    if f.is_finite() && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Ok(Value::Integer(f as i64))
+    } else {
+        Err(RuntimeErrorKind::InvalidNumber(format!("{:?}", f)))
+    }
+}
+
+// The larger (or smaller) of the receiver and the argument. A float receiver gives a float.
+fn pick(r: &Value, a: &Value, larger: bool) -> Result<Value, RuntimeErrorKind> {
+    let less = binary(TokenType::LessThan, r.clone(), a.clone())? == Value::Boolean(true);
+    let v = if less == larger { a } else { r };
+    match *r {
+        Value::Float(_) => Ok(Value::Float(to_float(v))),
+        _ => Ok(v.clone())
+    }
+}
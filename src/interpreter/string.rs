@@ -0,0 +1,53 @@
+// The natives of Std.String. Strings are UTF-8, and lengths and indexes count characters.
+use std::rc::Rc;
+use super::super::compiler::token::TokenType;
+use super::super::compiler::typecheck::Type;
+use super::native::Natives;
+use super::{RuntimeErrorKind, Value, binary};
+
+pub fn register(natives: &mut Natives) {
+    natives.register(Type::String, false, "length", |_, r, _| Ok(Value::Integer(text(r)?.chars().count() as i64)));
+    natives.register(Type::String, false, "at:", |_, r, a| {
+        let (s, i) = (text(r)?, integer(&a[0])?);
+        match s.chars().nth(i as usize) {
+            Some(c) if i >= 0 => Ok(Value::String(c.to_string())),
+            _ => Err(RuntimeErrorKind::OutOfRange(i, s.chars().count()))
+        }
+    });
+    natives.register(Type::String, false, "concat:", |_, r, a| binary(TokenType::Plus, r.clone(), a[0].clone()));
+    natives.register(Type::String, true, "slice:from:to:", |_, _, a| {
+        let s = text(&a[0])?;
+        let (from, to) = (integer(&a[1])?, integer(&a[2])?);
+        let length = s.chars().count();
+        for &i in &[from, to] {
+            if i < 0 || i as usize > length {
+                return Err(RuntimeErrorKind::OutOfRange(i, length))
+            }
+        }
+        Ok(Value::String(s.chars().skip(from as usize).take((to - from).max(0) as usize).collect()))
+    });
+    natives.register(Type::String, false, "contains:", |_, r, a| Ok(Value::Boolean(text(r)?.contains(text(&a[0])?))));
+    natives.register(Type::String, false, "split:", |_, r, a| {
+        let (s, separator) = (text(r)?, text(&a[0])?);
+        // An empty separator splits into characters
+        let parts = match separator {
+            "" => s.chars().map(|c| Value::String(c.to_string())).collect(),
+            separator => s.split(separator).map(|p| Value::String(p.to_string())).collect()
+        };
+        Ok(Value::Array(Rc::new(parts)))
+    });
+}
+
+fn text(v: &Value) -> Result<&str, RuntimeErrorKind> {
+    match *v {
+        Value::String(ref s) => Ok(s),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::String, v.get_type()))
+    }
+}
+
+fn integer(v: &Value) -> Result<i64, RuntimeErrorKind> {
+    match *v {
+        Value::Integer(i) => Ok(i),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::Integer, v.get_type()))
+    }
+}
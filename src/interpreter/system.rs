@@ -0,0 +1,33 @@
+// The natives of Std.System: how the program was launched, and the process it runs in
+use std::convert::TryFrom;
+use std::env;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::super::compiler::typecheck::Type;
+use super::native::Natives;
+use super::{RuntimeErrorKind, Value};
+
+pub fn register(natives: &mut Natives) {
+    let system = Type::Instance("Std.System".to_string(), "System".to_string());
+    natives.register(system.clone(), true, "isScript", |rt, _, _| Ok(Value::Boolean(rt.context().launch.script)));
+    natives.register(system.clone(), true, "args", |rt, _, _| {
+        let args = rt.context().launch.args.iter().map(|a| Value::String(a.clone())).collect();
+        Ok(Value::Array(Rc::new(args)))
+    });
+    natives.register(system.clone(), true, "env:", |_, _, a| match a[0] {
+        Value::String(ref name) => Ok(Value::String(env::var(name).unwrap_or_default())),
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::String, v.get_type()))
+    });
+    // The program stops like on an error, and whoever runs it exits the process
+    natives.register(system.clone(), true, "exit:", |_, _, a| match a[0] {
+        Value::Integer(code) => match i32::try_from(code) {
+            Ok(code) => Err(RuntimeErrorKind::Exit(code)),
+            Err(_) => Err(RuntimeErrorKind::InvalidExitCode(code))
+        },
+        ref v => Err(RuntimeErrorKind::InvalidArgument(Type::Integer, v.get_type()))
+    });
+    natives.register(system, true, "time", |_, _, _| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        Ok(Value::Float(now))
+    });
+}
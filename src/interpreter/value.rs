@@ -0,0 +1,147 @@
+// The values a running Kobold program works with
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::rc::Rc;
+use super::super::compiler::token::TokenType;
+use super::super::compiler::typecheck::Type;
+use super::RuntimeErrorKind;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Nothing,
+    Instance(Rc<Instance>), // Instances are never changed once built, so they are shared
+    Class(Type), // The struct itself, the receiver of calls
+    // Collections never change either. Maps keep their pairs, and sets their elements, in the order
+    // they were added, without repeats.
+    Array(Rc<Vec<Value>>),
+    Map(Rc<Vec<(Value, Value)>>),
+    Set(Rc<Vec<Value>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub struct_type: Type,
+    pub members: BTreeMap<String, Value>,
+}
+
+impl Value {
+    pub fn get_type(&self) -> Type {
+        match *self {
+            Value::Integer(_) => Type::Integer,
+            Value::Float(_) => Type::Float,
+            Value::Boolean(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+            Value::Nothing => Type::Nothing,
+            Value::Instance(ref i) => i.struct_type.clone(),
+            Value::Class(ref t) => Type::Class(Box::new(t.clone())),
+            // Collections are of the type of their first element, and empty ones of Nothing
+            Value::Array(ref a) => Type::Array(Box::new(element_type(a.first()))),
+            Value::Map(ref m) => Type::Map(Box::new(element_type(m.first().map(|p| &p.0))), Box::new(element_type(m.first().map(|p| &p.1)))),
+            Value::Set(ref s) => Type::Set(Box::new(element_type(s.first()))),
+        }
+    }
+}
+
+fn element_type(v: Option<&Value>) -> Type {
+    v.map(Value::get_type).unwrap_or(Type::Nothing)
+}
+
+fn write_elements(fmt: &mut Formatter, elements: &[Value]) -> FmtResult {
+    write!(fmt, "{{")?;
+    for (n, e) in elements.iter().enumerate() {
+        write!(fmt, "{}{}", if n > 0 { ", " } else { "" }, e)?;
+    }
+    write!(fmt, "}}")
+}
+
+impl Display for Value {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match *self {
+            Value::Integer(i) => write!(fmt, "{}", i),
+            Value::Float(f) => write!(fmt, "{:?}", f), // Keeps the decimal point of 2.0
+            Value::Boolean(b) => write!(fmt, "{}", b),
+            Value::String(ref s) => write!(fmt, "{}", s),
+            Value::Nothing => write!(fmt, "Nothing"),
+            Value::Instance(ref i) => {
+                write!(fmt, "{} {{", i.struct_type)?;
+                for (n, (name, value)) in i.members.iter().enumerate() {
+                    write!(fmt, "{}{}: {}", if n > 0 { ", " } else { "" }, name, value)?;
+                }
+                write!(fmt, "}}")
+            },
+            Value::Class(ref t) => write!(fmt, "{} class", t),
+            Value::Array(ref a) => write_elements(fmt, a),
+            Value::Map(ref m) => {
+                write!(fmt, "Map {{")?;
+                for (n, (k, v)) in m.iter().enumerate() {
+                    write!(fmt, "{}{} -> {}", if n > 0 { ", " } else { "" }, k, v)?;
+                }
+                write!(fmt, "}}")
+            },
+            Value::Set(ref s) => {
+                write!(fmt, "Set ")?;
+                write_elements(fmt, s)
+            },
+        }
+    }
+}
+
+// The prefix operators: - and +, on numbers
+pub fn unary(op: TokenType, v: Value) -> Result<Value, RuntimeErrorKind> {
+    match (op, v) {
+        (TokenType::Minus, Value::Integer(i)) => i.checked_neg().map(Value::Integer).ok_or(RuntimeErrorKind::Overflow(op)),
+        (TokenType::Minus, Value::Float(f)) => Ok(Value::Float(-f)),
+        (TokenType::Plus, v @ Value::Integer(_)) | (TokenType::Plus, v @ Value::Float(_)) => Ok(v),
+        (op, v) => Err(RuntimeErrorKind::InvalidOperand(op, v.get_type()))
+    }
+}
+
+// Arithmetic and comparisons. Integers stay integers, and become floats when mixed with floats.
+// Strings can be joined with +.
+pub fn binary(op: TokenType, l: Value, r: Value) -> Result<Value, RuntimeErrorKind> {
+    match (l, r) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let result = match op {
+                TokenType::Plus => a.checked_add(b),
+                TokenType::Minus => a.checked_sub(b),
+                TokenType::Asterisk => a.checked_mul(b),
+                TokenType::Backslash if b == 0 => return Err(RuntimeErrorKind::DivisionByZero),
+                TokenType::Backslash => a.checked_div(b),
+                TokenType::LessThan => return Ok(Value::Boolean(a < b)),
+                TokenType::GreaterThan => return Ok(Value::Boolean(a > b)),
+                TokenType::DoubleEqual => return Ok(Value::Boolean(a == b)),
+                _ => return Err(RuntimeErrorKind::InvalidOperands(op, Type::Integer, Type::Integer))
+            };
+            result.map(Value::Integer).ok_or(RuntimeErrorKind::Overflow(op))
+        },
+        (l, r) => match (as_float(&l), as_float(&r)) {
+            (Some(a), Some(b)) => match op {
+                TokenType::Plus => Ok(Value::Float(a + b)),
+                TokenType::Minus => Ok(Value::Float(a - b)),
+                TokenType::Asterisk => Ok(Value::Float(a * b)),
+                TokenType::Backslash => Ok(Value::Float(a / b)),
+                TokenType::LessThan => Ok(Value::Boolean(a < b)),
+                TokenType::GreaterThan => Ok(Value::Boolean(a > b)),
+                TokenType::DoubleEqual => Ok(Value::Boolean(a == b)),
+                _ => Err(RuntimeErrorKind::InvalidOperands(op, l.get_type(), r.get_type()))
+            },
+            _ if op == TokenType::DoubleEqual && l.get_type() == r.get_type() => Ok(Value::Boolean(l == r)),
+            _ => match (l, r) {
+                (Value::String(a), Value::String(b)) if op == TokenType::Plus => Ok(Value::String(a + &b)),
+                (l, r) => Err(RuntimeErrorKind::InvalidOperands(op, l.get_type(), r.get_type()))
+            }
+        }
+    }
+}
+
+fn as_float(v: &Value) -> Option<f64> {
+    match *v {
+        Value::Integer(i) => Some(i as f64),
+        Value::Float(f) => Some(f),
+        _ => None
+    }
+}
@@ -0,0 +1,108 @@
+// Kobold as a library: the compiler (lexer, parser, modules and checks), the interpreter and the VM
+// that run modules, the REPL, and the Runtime for programs that embed Kobold. The kobold binary is
+// a command line around it.
+pub mod compiler;
+pub mod interpreter;
+pub mod vm;
+pub mod repl;
+pub mod runtime;
+#[cfg(test)]
+mod tests;
+
+use compiler::Module;
+use compiler::ast::Expression;
+use compiler::lexer::{SyntaxError, SyntaxErrorKind};
+use compiler::library::{Library, LibraryError, LibraryErrorKind};
+use std::io::{BufRead, Read};
+use std::path::Path;
+
+pub use compiler::ast;
+pub use compiler::{Lexer, LexerConfig, ModuleManager, Parser};
+pub use interpreter::{Ctx, Value};
+pub use runtime::Runtime;
+
+// The errors of each stage, in one place for the tools that report them
+pub mod diagnostics {
+    pub use super::compiler::dispatch::DispatchError;
+    pub use super::compiler::graph::CycleError;
+    pub use super::compiler::lexer::{SyntaxError, SyntaxErrorKind};
+    pub use super::compiler::library::{LibraryError, LibraryErrorKind};
+    pub use super::compiler::resolver::{ResolveError, ResolveErrorKind};
+    pub use super::compiler::symbols::DuplicateError;
+    pub use super::compiler::trie::TrieError;
+    pub use super::compiler::typecheck::{TypeError, TypeErrorKind};
+    pub use super::interpreter::{RuntimeError, RuntimeErrorKind};
+}
+
+// Loads a module from its source, and gives its name
+pub fn load_module<R: BufRead>(file: &str, rdr: R, mman: &mut ModuleManager) -> Result<String, SyntaxError> {
+    let error = |line, kind| SyntaxError { file: file.to_string(), line, kind };
+    let ts = Lexer::new(file, rdr).process()?;
+    let mut module_code = Parser::new(file, ts).parse_top()?;
+    if module_code.is_empty() {
+        return Err(error(1, SyntaxErrorKind::NoModuleDeclaration))
+    }
+
+    match *module_code.remove(0) {
+        Expression::ModuleDeclaration(line, name, exports) => {
+            // Check the rest of the code, and prevent duplicate declarations
+            for inst in &module_code {
+                if let Expression::ModuleDeclaration(aline, ref aname, _) = **inst {
+                    return Err(error(aline, SyntaxErrorKind::ModuleRedeclared(name, aname.clone())))
+                }
+            }
+            let nmod = Module::new(&name, module_code).with_file(file).with_exports(exports);
+            for e in nmod.get_exports().unwrap_or(vec![]) {
+                if !nmod.declares_struct(&e) {
+                    return Err(error(line, SyntaxErrorKind::UndeclaredExport(name, e)))
+                }
+            }
+            if mman.get_module(&name).is_some() {
+                return Err(error(line, SyntaxErrorKind::NameTaken(name)))
+            }
+            mman.add_module(&name, nmod);
+            Ok(name)
+        },
+        _ => Err(error(1, SyntaxErrorKind::NoModuleDeclaration))
+    }
+}
+
+// Loads a script: a module named after its file, whose top-level statements are its main block.
+// Gives its name.
+pub fn load_script<R: BufRead>(file: &str, rdr: R, mman: &mut ModuleManager) -> Result<String, SyntaxError> {
+    let ts = Lexer::new(file, rdr).process()?;
+    let mut code: Vec<Box<Expression>> = vec![];
+    let mut body: Vec<Box<Expression>> = vec![];
+    for e in Parser::new(file, ts).parse_script()? {
+        match *e {
+            Expression::StructDeclaration { .. } | Expression::MessageDeclaration { .. } | Expression::ImportDeclaration { .. } => code.push(e),
+            _ => body.push(e)
+        }
+    }
+    code.push(Box::new(Expression::MainDeclaration { body, line: 1 }));
+    let name = match script_name(file) {
+        Some(name) => name,
+        None => return Err(SyntaxError { file: file.to_string(), line: 1, kind: SyntaxErrorKind::NotAScript })
+    };
+    if mman.get_module(&name).is_some() {
+        return Err(SyntaxError { file: file.to_string(), line: 1, kind: SyntaxErrorKind::NameTaken(name) })
+    }
+    mman.add_module(&name, Module::new(&name, code).with_file(file).with_script(true));
+    Ok(name)
+}
+
+// The module name of a script: its file name, without the extension. None for a path without a file name.
+pub fn script_name(file: &str) -> Option<String> {
+    Path::new(file).file_stem().and_then(|s| s.to_str()).map(|stem| stem.to_string())
+}
+
+// Loads a precompiled library, which was checked when it was compiled, and gives its name
+pub fn load_library<R: Read>(file: &str, mut rdr: R, mman: &mut ModuleManager) -> Result<String, LibraryError> {
+    let lib = Library::read(file, &mut rdr)?;
+    let name = lib.name.clone();
+    if mman.get_module(&name).is_some() {
+        return Err(LibraryError { file: file.to_string(), kind: LibraryErrorKind::NameTaken(name) })
+    }
+    mman.add_module(&name, lib.into_module());
+    Ok(name)
+}
@@ -1,23 +1,27 @@
 extern crate argparse;
+extern crate kobold;
 
-mod compiler;
-#[cfg(test)]
-mod tests;
-
-use compiler::Lexer;
-use compiler::Parser;
-use compiler::{Module, ModuleManager};
-use compiler::ast::Expression;
+use kobold::compiler::{Module, ModuleManager, Resolver, SymbolTable, TypeChecker};
+use kobold::compiler::library::{Library, LibraryErrorKind};
+use kobold::interpreter::{Interpreter, RuntimeError, RuntimeErrorKind, Value};
+use kobold::vm::{self, Compiler, Vm};
+use kobold::{load_library, load_module, load_script, repl, script_name};
 use std::io::BufReader;
 use std::fs::File;
 use std::path::Path;
+use std::time::Instant;
 use std::fs;
+use std::process;
 
-use argparse::{ArgumentParser, Print, List};
+use argparse::{ArgumentParser, Print, List, Store, StoreTrue};
 
 struct Options {
     classpath: Vec<String>,
     excludes: Vec<String>,
+    graph: bool,
+    vm: bool,
+    command: String,
+    arguments: Vec<String>,
 }
 
 fn select_files_in_directory(dir: &Path, excl:&Vec<&Path>, list: &mut Vec<String>) -> std::io::Result<()> {
@@ -71,43 +75,163 @@ fn classpath_filtering(excludes: &Vec<&Path>, a: &Path) -> bool {
     true
 }
 
-fn load_modules(flst: &Vec<String>, mman: &mut ModuleManager) {
-    for file in flst {
-        println!("File: {}", file);
+// Loads the sources before the libraries. A library of a module that is loaded from its source,
+// like the Main.klb that build writes next to Main.kbld, is skipped: the source is the one to run.
+fn load_modules(flst: &Vec<String>, mman: &mut ModuleManager, verbose: bool) {
+    let (libraries, sources): (Vec<&String>, Vec<&String>) = flst.iter().partition(|f| f.ends_with(".klb"));
+    for file in sources.into_iter().chain(libraries) {
+        if verbose {
+            println!("File: {}", file);
+        }
         let fs: File = File::open(file.clone()).ok().unwrap();
         let rdr = BufReader::new(fs);
-        let mut lex = Lexer::new(&file, rdr);
-        let ts = lex.process();
-        // println!("{:#?}", ts);
-        let mut parser = Parser::new(&file, ts);
-        let module_code = parser.parse_top();
-        // println!("{:?}", module_code);
-
-        match *module_code[0].clone() {
-            Expression::ModuleDeclaration(name) => {
-                // Add exports field... (maybe? after v1.0?)
-                // Check the rest of the code, and prevent duplicate declarations
-                let nmod_code: Vec<Box<Expression>> = module_code.iter().skip(1).cloned().collect();
-                for inst in nmod_code.iter().cloned() {
-                    match *inst {
-                        Expression::ModuleDeclaration(aname) => {
-                            panic!("Cannot declare module as {} and {} in file {}", name, aname, file);
-                        },
-                        _ => {},
-                    }
-                }
-                let nmod = Module::new(&name, nmod_code);
-                mman.add_module(&name, nmod);
-            },
-            _=>{panic!("Must declare module name.")}
+        if file.ends_with(".klb") {
+            match load_library(file, rdr, mman) {
+                Ok(_) => {},
+                Err(ref e) if matches!(e.kind, LibraryErrorKind::NameTaken(_)) => if verbose {
+                    println!("Skipped {}, its module is loaded from source", file);
+                },
+                Err(e) => panic!("{}", e)
+            }
+        } else if file.ends_with(".ksc") {
+            load_script(file, rdr, mman).unwrap_or_else(|e| panic!("{}", e));
+        } else {
+            load_module(file, rdr, mman).unwrap_or_else(|e| panic!("{}", e));
         }
     }
 }
 
+// Checks the declarations and resolves the imports of every loaded module, and reports all errors at once
+fn check_modules(mman: &ModuleManager) {
+    let resolver = Resolver::new(mman);
+    let mut errors: Vec<String> = vec![];
+    for m in mman.find_modules_under("") {
+        let (_, duplicates) = SymbolTable::collect(&m);
+        errors.extend(duplicates.iter().map(|e| e.to_string()));
+        errors.extend(resolver.check_module(&m).iter().map(|e| e.to_string()));
+    }
+    if !errors.is_empty() {
+        for e in errors.iter() {
+            println!("{}", e);
+        }
+        panic!("Could not resolve modules: {} error(s)", errors.len());
+    }
+    let modules = match mman.modules_in_order() {
+        Ok(modules) => modules,
+        Err(e) => {
+            println!("{}", e);
+            panic!("Could not order modules: {} import cycle(s)", e.cycles.len());
+        }
+    };
+
+    let checker = TypeChecker::new(mman);
+    for m in modules.iter() {
+        errors.extend(checker.check_module(m).iter().map(|e| e.to_string()));
+    }
+    if !errors.is_empty() {
+        for e in errors.iter() {
+            println!("{}", e);
+        }
+        panic!("Type checking failed: {} error(s)", errors.len());
+    }
+}
+
+// The module named after the command, Main by default
+fn module_argument(opts: &Options) -> String {
+    match opts.arguments.first() {
+        Some(a) if a.ends_with(".ksc") => script_name(a).unwrap_or_else(|| panic!("Not a script file: {}", a)),
+        Some(a) => a.clone(),
+        None => "Main".to_string()
+    }
+}
+
+// A script given by its file is loaded even when it is outside the classpath
+fn add_script_file(opts: &Options, list: &mut Vec<String>) {
+    let script = match opts.arguments.first() {
+        Some(a) if a.ends_with(".ksc") => a,
+        _ => return
+    };
+    let path = match fs::canonicalize(script) {
+        Ok(path) => path,
+        Err(e) => panic!("IO Error: {}: {}", script, e)
+    };
+    if !list.iter().any(|f| fs::canonicalize(f).ok().as_ref() == Some(&path)) {
+        list.push(script.clone());
+    }
+}
+
+fn find_module<'a>(mman: &'a ModuleManager, name: &str) -> &'a Module {
+    match mman.get_module(name) {
+        Some(m) => m,
+        None => panic!("Unknown module {}", name)
+    }
+}
+
+fn compile_module(mman: &ModuleManager, name: &str) -> vm::bytecode::Program {
+    match Compiler::new(mman).compile(find_module(mman, name)) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("{}", e);
+            panic!("Could not compile module {}", name);
+        }
+    }
+}
+
+fn report_error(name: &str, e: Box<RuntimeError>) -> ! {
+    println!("{}", e);
+    panic!("Module {} stopped with an error", name);
+}
+
+// Runs the main block of a module, and prints what it returns. The arguments go to the program.
+fn run_module(mman: &ModuleManager, name: &str, args: Vec<String>, on_vm: bool) {
+    let result = match on_vm {
+        true => Vm::new(mman, &compile_module(mman, name)).with_args(args).run(),
+        false => Interpreter::new(mman).with_args(args).run_main(find_module(mman, name)),
+    };
+    match result {
+        Ok(Value::Nothing) => {},
+        Ok(v) => println!("{}", v),
+        Err(e) => match e.kind {
+            RuntimeErrorKind::Exit(code) => process::exit(code),
+            _ => report_error(name, e)
+        }
+    }
+}
+
+// Times the main block of a module on the interpreter and on the VM. Compiling is not timed.
+fn bench_module(mman: &ModuleManager, name: &str, runs: u32) {
+    let m = find_module(mman, name);
+    let mut interpreter = Interpreter::new(mman);
+    let start = Instant::now();
+    let mut expected = Value::Nothing;
+    for _ in 0..runs {
+        expected = interpreter.run_main(m).unwrap_or_else(|e| report_error(name, e));
+    }
+    let interpreted = start.elapsed() / runs.max(1);
+
+    let program = compile_module(mman, name);
+    let mut machine = Vm::new(mman, &program);
+    let start = Instant::now();
+    for _ in 0..runs {
+        let found = machine.run().unwrap_or_else(|e| report_error(name, e));
+        if found != expected {
+            panic!("The VM gave {}, but the interpreter gave {}", found, expected);
+        }
+    }
+    let compiled = start.elapsed() / runs.max(1);
+    println!("{} run(s) of {}", runs, name);
+    println!("Interpreter: {:?} per run", interpreted);
+    println!("VM:          {:?} per run ({:.1}x)", compiled, interpreted.as_secs_f64() / compiled.as_secs_f64().max(1e-9));
+}
+
 fn main() {
     let mut opts = Options {
         classpath: vec![],
         excludes: vec![],
+        graph: false,
+        vm: false,
+        command: "check".to_string(),
+        arguments: vec![],
     };
     // Add classpathing...
     {
@@ -115,6 +239,13 @@ fn main() {
         ap.set_description("Kobold example parser");
         ap.refer(&mut opts.classpath).add_option(&["-c", "--classpath"], List, "Module Path (Default: .)");
         ap.refer(&mut opts.excludes).add_option(&["-e", "--excludes"], List, "Excludes from classpath");
+        ap.refer(&mut opts.graph).add_option(&["-g", "--graph"], StoreTrue, "Print the module import graph in DOT format");
+        ap.refer(&mut opts.vm).add_option(&["--vm"], StoreTrue, "Run on the bytecode VM instead of the interpreter");
+        ap.refer(&mut opts.command).add_argument("command", Store,
+            "check (the default); run MODULE [ARGS] to run its main block (Default: Main), with the arguments for Std.System; disasm MODULE to print its bytecode; \
+             bench MODULE [RUNS] to time the interpreter against the VM; build MODULE [FILE] to write it as a .klb library; \
+             repl to read and run code interactively");
+        ap.refer(&mut opts.arguments).add_argument("arguments", List, "Arguments of the command");
         ap.add_option(&["-v", "--version"], Print(env!("CARGO_PKG_VERSION").to_string()), "Program version");
         ap.parse_args_or_exit();
     }
@@ -126,17 +257,60 @@ fn main() {
     }
 
     let excludes: Vec<&Path> = opts.excludes.iter().map({|a| Path::new(&*a)}).collect();
-    for e in excludes.iter() {
-        println!("{}", e.display());
+    // Keep the output clean when it is meant for another program
+    let verbose = !opts.graph && opts.command == "check";
+    if verbose {
+        for e in excludes.iter() {
+            println!("{}", e.display());
+        }
     }
     let classpath: Vec<&Path> = opts.classpath.iter().map({|a| Path::new(&*a)}).collect();
 
-    println!("{:?}", classpath);
+    if verbose {
+        println!("{:?}", classpath);
+    }
 
     let mut files_list: Vec<String> = vec![];
     select_files(&classpath, &excludes, &mut files_list);
+    add_script_file(&opts, &mut files_list);
 
     let mut mman = ModuleManager::new();
-    load_modules(&files_list, &mut mman);
-    println!("{:#?}", mman);
+    load_modules(&files_list, &mut mman, verbose);
+    if opts.graph {
+        // Print the graph even with import cycles, it is the best way to find them
+        print!("{}", mman.dependency_graph().to_dot());
+        return
+    }
+    check_modules(&mman);
+    match opts.command.as_ref() {
+        "check" => println!("{:#?}", mman),
+        "run" => run_module(&mman, &module_argument(&opts), opts.arguments.iter().skip(1).cloned().collect(), opts.vm),
+        "disasm" => {
+            let name = module_argument(&opts);
+            let program = compile_module(&mman, &name);
+            print!("{}", vm::disassemble_program(&program));
+        },
+        "bench" => {
+            let runs = match opts.arguments.get(1) {
+                Some(runs) => runs.parse().expect("The number of runs must be a number"),
+                None => 100
+            };
+            bench_module(&mman, &module_argument(&opts), runs);
+        },
+        "build" => {
+            let name = module_argument(&opts);
+            let out = opts.arguments.get(1).cloned().unwrap_or(format!("{}.klb", name));
+            let mut f = File::create(&out).unwrap_or_else(|e| panic!("{}: {}", out, e));
+            if let Err(e) = Library::from_module(find_module(&mman, &name)).write(&mut f) {
+                panic!("{}: {}", out, e);
+            }
+        },
+        "repl" => {
+            let stdin = std::io::stdin();
+            if let Err(e) = repl::run(&mut mman, stdin.lock(), &mut std::io::stdout()) {
+                panic!("IO Error: {}", e);
+            }
+        },
+        other => panic!("Unknown command {}, expected check, run, disasm, bench, build or repl", other)
+    }
 }
@@ -0,0 +1,162 @@
+// An interactive session. Declarations are added to a module of their own, and statements are run
+// by the interpreter, with the lets of earlier inputs still bound. Commands start with a colon.
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use super::compiler::{Lexer, Module, ModuleManager, Parser, Resolver, SymbolTable, TypeChecker};
+use super::compiler::ast::Expression;
+use super::interpreter::{Interpreter, Value};
+
+const SESSION: &str = "Repl";
+const FILE: &str = "<repl>";
+
+pub struct Repl {
+    #[allow(clippy::vec_box)]
+    declarations: Vec<Box<Expression>>, // Structs, messages and imports, the code of the session module
+    bindings: HashMap<String, Value>,
+}
+
+impl Repl {
+    pub fn new(mman: &mut ModuleManager) -> Repl {
+        mman.add_module(SESSION, Module::new(SESSION, vec![]).with_file(FILE));
+        Repl { declarations: vec![], bindings: HashMap::new() }
+    }
+
+    // Runs one complete input, and gives what to print
+    pub fn eval(&mut self, mman: &mut ModuleManager, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if input.starts_with(':') {
+            let (command, rest) = match input.find(char::is_whitespace) {
+                Some(at) => (&input[..at], input[at..].trim()),
+                None => (input, "")
+            };
+            return match command {
+                ":load" => self.load(mman, rest),
+                ":type" => self.type_of(mman, rest),
+                ":ast" => Ok(parse(rest)?.iter().map(|e| format!("{:#?}", e)).collect::<Vec<_>>().join("\n")),
+                _ => Err(format!("Unknown command {}", command))
+            }
+        }
+        let (declarations, statements): (Vec<_>, Vec<_>) = parse(input)?.into_iter().partition(|e| matches!(**e,
+            Expression::StructDeclaration { .. } | Expression::MessageDeclaration { .. } | Expression::ImportDeclaration { .. }));
+        if !declarations.is_empty() {
+            self.declare(mman, &declarations)?;
+        }
+        if statements.is_empty() {
+            return Ok(String::new())
+        }
+        let m = mman.get_module(SESSION).unwrap();
+        let types = self.bindings.iter().map(|(name, v)| (name.clone(), v.get_type())).collect();
+        let (_, errors) = TypeChecker::new(mman).check_statements(m, types, &statements);
+        if !errors.is_empty() {
+            return Err(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))
+        }
+        match Interpreter::new(mman).run_statements(m, &mut self.bindings, &statements) {
+            Ok(Value::Nothing) => Ok(String::new()),
+            Ok(v) => Ok(v.to_string()),
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    // Imports a module of the classpath into the session
+    fn load(&mut self, mman: &mut ModuleManager, name: &str) -> Result<String, String> {
+        if name.is_empty() || name == SESSION || mman.get_module(name).is_none() {
+            return Err(format!("Unknown module {}", name))
+        }
+        let import = Expression::ImportDeclaration { module: name.to_string(), names: None, line: 1 };
+        self.declare(mman, &[Box::new(import)])?;
+        Ok(format!("Loaded {}", name))
+    }
+
+    fn type_of(&self, mman: &ModuleManager, input: &str) -> Result<String, String> {
+        let code = parse(input)?;
+        let m = mman.get_module(SESSION).unwrap();
+        let types = self.bindings.iter().map(|(name, v)| (name.clone(), v.get_type())).collect();
+        match TypeChecker::new(mman).check_statements(m, types, &code) {
+            (_, ref errors) if !errors.is_empty() => Err(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")),
+            (Some(t), _) => Ok(t.to_string()),
+            (None, _) => Err(format!("{} is not an expression", input))
+        }
+    }
+
+    // Adds declarations to the session module. They are kept only if the module still checks.
+    fn declare(&mut self, mman: &mut ModuleManager, declarations: &[Box<Expression>]) -> Result<(), String> {
+        let mut code = self.declarations.clone();
+        code.extend_from_slice(declarations);
+        let old = mman.remove_module(SESSION).unwrap();
+        mman.add_module(SESSION, Module::new(SESSION, code.clone()).with_file(FILE));
+        let errors = {
+            let m = mman.get_module(SESSION).unwrap();
+            let mut errors = SymbolTable::collect(m).1.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            errors.extend(Resolver::new(mman).check_module(m).iter().map(|e| e.to_string()));
+            if errors.is_empty() {
+                errors.extend(TypeChecker::new(mman).check_module(m).iter().map(|e| e.to_string()));
+            }
+            errors
+        };
+        if !errors.is_empty() {
+            mman.remove_module(SESSION);
+            mman.add_module(SESSION, old);
+            return Err(errors.join("\n"))
+        }
+        self.declarations = code;
+        Ok(())
+    }
+}
+
+// Gives the code as the parser does, boxed
+#[allow(clippy::vec_box)]
+fn parse(input: &str) -> Result<Vec<Box<Expression>>, String> {
+    let source = input.to_string() + "\n";
+    let ts = Lexer::new(FILE, source.as_bytes()).process().map_err(|e| e.to_string())?;
+    Parser::new(FILE, ts).parse_script().map_err(|e| e.to_string())
+}
+
+// Whether an input goes on to the next line: it opens more [ and { than it closes
+pub fn is_open(input: &str) -> bool {
+    let mut depth = 0;
+    let mut string = false;
+    let mut comment = false;
+    for c in input.chars() {
+        match c {
+            '\n' => comment = false,
+            _ if comment => {},
+            '"' => string = !string,
+            _ if string => {},
+            '#' => comment = true,
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+// Reads inputs until the end of the input, or :quit
+pub fn run<R: BufRead, W: Write>(mman: &mut ModuleManager, mut input: R, output: &mut W) -> io::Result<()> {
+    let mut repl = Repl::new(mman);
+    loop {
+        let mut text = String::new();
+        write!(output, "> ")?;
+        loop {
+            output.flush()?;
+            if input.read_line(&mut text)? == 0 {
+                return Ok(())
+            }
+            if !is_open(&text) {
+                break
+            }
+            write!(output, "... ")?;
+        }
+        if text.trim().is_empty() {
+            continue
+        }
+        if text.trim() == ":quit" {
+            return Ok(())
+        }
+        match repl.eval(mman, &text) {
+            Ok(ref shown) if shown.is_empty() => {},
+            Ok(shown) => writeln!(output, "{}", shown)?,
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+    }
+}
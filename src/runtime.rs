@@ -0,0 +1,114 @@
+// The API for Rust programs that embed Kobold: load modules from their source, give Rust functions to
+// the messages of their structs, and send messages. The std modules are always loaded.
+use super::compiler::{ModuleManager, Resolver, SymbolTable, TypeChecker};
+use super::compiler::dispatch::{Dispatcher, DispatchTable};
+use super::compiler::typecheck::Type;
+use super::interpreter::{HostNative, Interpreter, Natives, RuntimeError, Value};
+use super::load_module;
+use std::rc::Rc;
+
+const FILE: &str = "<embedded>";
+
+const STD: [(&str, &str); 5] = [
+    ("std/collection.kbld", include_str!("../std/collection.kbld")),
+    ("std/io.kbld", include_str!("../std/io.kbld")),
+    ("std/number.kbld", include_str!("../std/number.kbld")),
+    ("std/string.kbld", include_str!("../std/string.kbld")),
+    ("std/system.kbld", include_str!("../std/system.kbld")),
+];
+
+pub struct Runtime {
+    mman: ModuleManager,
+    natives: Rc<Natives>,
+    table: Rc<DispatchTable>, // Of the loaded modules, indexed again when they change
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Runtime {
+        let mut mman = ModuleManager::new();
+        for &(file, src) in STD.iter() {
+            load_module(file, src.as_bytes(), &mut mman).unwrap_or_else(|e| panic!("{}", e));
+        }
+        let table = Dispatcher::new(&mman).table();
+        Runtime { mman, natives: Rc::new(Natives::new()), table }
+    }
+
+    // Runs f for a message (or call) of a struct, instead of the body of its declaration. The struct is
+    // given by its full name, like Host.Clock, or is a builtin, like String. A name without its module
+    // is looked up in the loaded modules, and must be declared by only one of them. The struct itself must
+    // declare the message or call: an inherited one runs the native of the struct that declares it.
+    pub fn register_native(&mut self, struct_name: &str, selector: &str, f: HostNative) -> Result<(), String> {
+        let t = match Type::builtin(struct_name) {
+            Some(t) => t,
+            None => {
+                let (module, name) = match struct_name.rfind('.') {
+                    Some(dot) => (struct_name[..dot].to_string(), &struct_name[dot + 1..]),
+                    None => {
+                        let modules = self.mman.find_modules_under("").into_iter()
+                            .filter(|m| m.declares_struct(struct_name)).map(|m| m.get_full_name()).collect::<Vec<_>>();
+                        match modules.len() {
+                            0 => return Err(format!("Unknown struct {}", struct_name)),
+                            1 => (modules[0].clone(), struct_name),
+                            _ => return Err(format!("Ambiguous name {}, declared in {}", struct_name, modules.join(", ")))
+                        }
+                    }
+                };
+                match self.mman.get_module(&module) {
+                    Some(m) if m.declares_struct(name) => Type::Instance(module, name.to_string()),
+                    _ => return Err(format!("Unknown struct {}", struct_name))
+                }
+            }
+        };
+        let dispatcher = Dispatcher::with_table(&self.mman, self.table.clone());
+        let sides = [false, true].iter().cloned().filter(|&is_call| {
+            let receiver = if is_call { Type::Class(Box::new(t.clone())) } else { t.clone() };
+            dispatcher.resolve(&receiver, selector).map(|d| d.owner == t).unwrap_or(false)
+        }).collect::<Vec<_>>();
+        if sides.is_empty() {
+            return Err(format!("{} declares no message [{}]", struct_name, selector))
+        }
+        for is_call in sides {
+            Rc::make_mut(&mut self.natives).register_host(t.clone(), is_call, selector, f);
+        }
+        Ok(())
+    }
+
+    // Loads a module, and checks it with the modules loaded before. Gives its name, or its errors,
+    // in which case it is not kept.
+    pub fn load_module(&mut self, src: &str) -> Result<String, Vec<String>> {
+        let name = load_module(FILE, src.as_bytes(), &mut self.mman).map_err(|e| vec![e.to_string()])?;
+        let errors = {
+            let m = self.mman.get_module(&name).unwrap();
+            let mut errors = SymbolTable::collect(m).1.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            errors.extend(Resolver::new(&self.mman).check_module(m).iter().map(|e| e.to_string()));
+            if errors.is_empty() {
+                errors.extend(TypeChecker::new(&self.mman).check_module(m).iter().map(|e| e.to_string()));
+            }
+            errors
+        };
+        if !errors.is_empty() {
+            self.mman.remove_module(&name);
+            return Err(errors)
+        }
+        self.table = Dispatcher::new(&self.mman).table();
+        Ok(name)
+    }
+
+    // The struct of a loaded module, as a receiver of calls
+    pub fn class(&self, module: &str, name: &str) -> Option<Value> {
+        match self.mman.get_module(module) {
+            Some(m) if m.declares_struct(name) => Some(Value::Class(Type::Instance(m.get_full_name(), name.to_string()))),
+            _ => None
+        }
+    }
+
+    pub fn send(&self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, Box<RuntimeError>> {
+        Interpreter::sharing(&self.mman, self.table.clone(), self.natives.clone()).send_message(receiver, selector, args)
+    }
+}
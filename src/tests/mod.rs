@@ -1,5 +1,36 @@
 use super::compiler::trie::Trie;
 use super::compiler::trie::TrieError;
+use super::compiler::lexer::SyntaxErrorKind;
+use super::compiler::{Lexer, LexerConfig, Module, ModuleManager, Parser, Resolver, SymbolTable, TypeChecker};
+use super::compiler::dispatch::{Dispatcher, DispatchError};
+use super::compiler::typecheck::{Type, TypeErrorKind};
+use super::compiler::ast::Expression;
+use super::compiler::token::{TokenStream, TokenType};
+use super::compiler::symbols::SymbolKind;
+use super::compiler::resolver::ResolveErrorKind;
+use super::interpreter::{Interpreter, Io, MAX_DEPTH, RuntimeError, RuntimeErrorKind, Value};
+use super::interpreter::io::Stream;
+use super::vm::{Compiler, Vm};
+use super::vm::bytecode::{Constant, Opcode, Target};
+use super::vm::disasm::disassemble;
+use super::compiler::library::{Library, LibraryErrorKind, StructLayout};
+use super::{load_library, load_module, load_script, script_name};
+use super::repl::{self, Repl, is_open};
+use super::runtime::Runtime;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::io;
+use std::rc::Rc;
+use std::thread;
+
+fn load_sources(sources: &[(&str, &str)]) -> ModuleManager {
+    let mut mman = ModuleManager::new();
+    for &(file, src) in sources {
+        load_module(file, src.as_bytes(), &mut mman).unwrap_or_else(|e| panic!("{}", e));
+    }
+    mman
+}
 
 #[test]
 fn test_trie() {
@@ -25,3 +56,1195 @@ fn test_trie() {
     assert_eq!(t.search("y").ok(), Some(5));
     assert_eq!(t.search("tree").ok(), Some(2));
 }
+
+fn token_types(mut ts: TokenStream) -> Vec<TokenType> {
+    let n = ts.len();
+    ts.read(n).iter().map(|t| t.get_type()).collect()
+}
+
+#[test]
+fn test_lexer_config() {
+    let source = &b"unless x => y module\n"[..];
+    let dialect = Rc::new(LexerConfig::kobold().with_keyword("unless", TokenType::If).with_operator("=>", TokenType::Arrow));
+    assert_eq!(token_types(Lexer::new("a", source).with_config(dialect.clone()).process().unwrap()),
+        vec![TokenType::If, TokenType::Identifier, TokenType::Arrow, TokenType::Identifier, TokenType::Module, TokenType::EndOfFile]);
+    // The trie of the config is kept between lexers
+    assert_eq!(token_types(Lexer::new("b", &b"a => b\n"[..]).with_config(dialect).process().unwrap()),
+        vec![TokenType::Identifier, TokenType::Arrow, TokenType::Identifier, TokenType::EndOfFile]);
+    assert_eq!(token_types(Lexer::new("c", source).with_config(Rc::new(LexerConfig::new().with_operator("=>", TokenType::Arrow))).process().unwrap()),
+        vec![TokenType::Identifier, TokenType::Identifier, TokenType::Arrow, TokenType::Identifier, TokenType::Identifier, TokenType::EndOfFile]);
+}
+
+// A source that never ends
+struct Endless;
+
+impl io::Read for Endless {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = if i % 2 == 0 { b'x' } else { b'\n' };
+        }
+        Ok(buf.len() / 2 * 2)
+    }
+}
+
+#[test]
+fn test_lexer_streaming() {
+    let tokens = Lexer::new("endless", io::BufReader::new(Endless)).take(1000).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(tokens.len(), 1000);
+    assert_eq!((tokens[999].get_string(), tokens[999].get_line()), ("x".to_string(), 1000));
+
+    let source = "let a = [b plus: 12] -> c\n".repeat(50000);
+    let mut ts = Lexer::new("long", source.as_bytes()).process().unwrap();
+    assert_eq!(ts.len(), 550001);
+    assert_eq!(ts.read(3).iter().map(|t| t.get_type()).collect::<Vec<_>>(), vec![TokenType::Let, TokenType::Identifier, TokenType::Equal]);
+    assert_eq!(ts.len(), 549998);
+}
+
+// The last token of a source without a newline at the end, and the EndOfFile after it
+fn last_tokens(source: &str) -> Vec<(TokenType, String)> {
+    let tokens = Lexer::new("end", source.as_bytes()).map(|t| t.map(|t| (t.get_type(), t.get_string()))).collect::<Result<Vec<_>, _>>().unwrap();
+    tokens[tokens.len().saturating_sub(2)..].to_vec()
+}
+
+#[test]
+fn test_lexer_end_of_file() {
+    let eof = (TokenType::EndOfFile, String::new());
+    assert_eq!(last_tokens("let y = 3 + 5"), vec![(TokenType::Integer, "5".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("a = 54.08"), vec![(TokenType::Float, "54.08".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("return y"), vec![(TokenType::Identifier, "y".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("x = true"), vec![(TokenType::True, "true".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("use Std.IO"), vec![(TokenType::StructIdentifier, "IO".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("x = \"text\""), vec![(TokenType::CString, "text".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("1 =="), vec![(TokenType::DoubleEqual, "==".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("1 -"), vec![(TokenType::Minus, "-".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("operator infix <+>"), vec![(TokenType::UserOperator(0), "<+>".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("x # comment"), vec![(TokenType::Identifier, "x".to_string()), eof.clone()]);
+    assert_eq!(last_tokens("x\r"), vec![(TokenType::Identifier, "x".to_string()), eof.clone()]);
+    assert_eq!(last_tokens(""), vec![eof.clone()]);
+
+    let mut lexer = Lexer::new("end", &b"x\n\n"[..]);
+    assert_eq!(lexer.nth(1).map(|t| t.map(|t| (t.get_type(), t.get_line()))), Some(Ok((TokenType::EndOfFile, 3))));
+    assert!(lexer.next().is_none());
+
+    // Without the newline, the last statement of main used to be dropped
+    let mut mman = ModuleManager::new();
+    load_script("end.ksc", &b"let y = 3 + 5\nreturn y + 1"[..], &mut mman).unwrap();
+    assert_eq!(run_main(&mman, "end"), Ok(Value::Integer(9)));
+}
+
+#[test]
+fn test_lexer_unterminated_string() {
+    let e = Lexer::new("end", &b"x = \"text"[..]).process().unwrap_err();
+    assert_eq!(e.to_string(), "end:1: Unterminated string \"text");
+}
+
+#[test]
+fn test_lexer_unfinished_operator() {
+    let config = Rc::new(LexerConfig::new().with_operator("***", TokenType::Power));
+    let e = Lexer::new("end", &b"2 **"[..]).with_config(config).process().unwrap_err();
+    assert_eq!(e.to_string(), "end:1: Error lexing **");
+}
+
+#[test]
+fn test_lexer_error_ends_tokens() {
+    let mut lexer = Lexer::new("end", &b"x `y\n"[..]);
+    assert_eq!(lexer.next().map(|t| t.map(|t| t.get_type())), Some(Ok(TokenType::Identifier)));
+    assert_eq!(lexer.next().and_then(|t| t.err()).map(|e| e.kind), Some(SyntaxErrorKind::UnknownSymbol('`')));
+    assert!(lexer.next().is_none());
+}
+
+#[test]
+fn test_module_manager() {
+    let mut mman = ModuleManager::new();
+    for name in &["Main", "Std.Number", "Std.String", "A.B.C", "A.B", "Std"] {
+        mman.add_module(name, Module::new(name, vec![]));
+    }
+
+    assert_eq!(mman.find_module("Main").map(|m| m.get_full_name()), Some("Main".to_string()));
+    assert_eq!(mman.find_module("Std.Number").map(|m| m.get_full_name()), Some("Std.Number".to_string()));
+    assert_eq!(mman.find_module("A.B.C").map(|m| m.get_full_name()), Some("A.B.C".to_string()));
+    assert_eq!(mman.find_module("A.B").map(|m| m.get_full_name()), Some("A.B".to_string()));
+    assert_eq!(mman.find_module("Std").map(|m| m.get_full_name()), Some("Std".to_string()));
+    assert!(mman.find_module("A").is_none());
+    assert!(mman.find_module("Std.Nothing").is_none());
+    assert!(mman.find_module("").is_none());
+
+    let names = |v: Vec<Module>| v.iter().map(|m| m.get_full_name()).collect::<Vec<String>>();
+    assert_eq!(names(mman.find_modules_under("Std")), vec!["Std", "Std.Number", "Std.String"]);
+    assert_eq!(names(mman.find_modules_under("A")), vec!["A.B", "A.B.C"]);
+    assert_eq!(names(mman.find_modules_under("Main")), vec!["Main"]);
+    assert!(mman.find_modules_under("Nothing").is_empty());
+    assert_eq!(mman.find_modules_under("").len(), 6);
+}
+
+#[test]
+#[should_panic(expected = "Name already taken: Std.Number")]
+fn test_module_manager_duplicate() {
+    let mut mman = ModuleManager::new();
+    mman.add_module("Std.Number", Module::new("Std.Number", vec![]));
+    mman.add_module("Std.Number", Module::new("Std.Number", vec![]));
+}
+
+#[test]
+fn test_resolve_imports() {
+    let mman = load_sources(&[
+        ("Number.kbld", "module Std.Number;\nstruct Number {}\nstruct Integer {}\n"),
+        ("String.kbld", "module Std.String;\nstruct String {}\n"),
+        ("Main.kbld", "module Main;\nimport Std.String;\nuse Std.Number (Number);\nstruct Main {}\nmessage Main [name] -> String {}\nlet x: Number = 3\n"),
+    ]);
+    let resolver = Resolver::new(&mman);
+    let main = mman.find_module("Main").unwrap();
+
+    assert!(resolver.check_module(&main).is_empty());
+    assert_eq!(resolver.resolve_struct(&main, "Main", 0), Ok("Main".to_string()));
+    assert_eq!(resolver.resolve_struct(&main, "String", 0), Ok("Std.String".to_string()));
+    assert_eq!(resolver.resolve_struct(&main, "Number", 0), Ok("Std.Number".to_string()));
+    // Only Number was listed from Std.Number
+    assert_eq!(resolver.resolve_struct(&main, "Integer", 4).unwrap_err().kind, ResolveErrorKind::UnknownStruct("Integer".to_string()));
+}
+
+#[test]
+fn test_builtin_declarations() {
+    let string = ("string.kbld", include_str!("../../std/string.kbld"));
+    let mman = load_sources(&[string, ("Main.kbld", "module Main;\nstruct Foo {}\nstruct String [Foo] {}\n\
+        message Foo [hello] -> Integer { return 1 }\n")]);
+    let errors = Resolver::new(&mman).check_module(&mman.find_module("Main").unwrap());
+    assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        vec!["Main.kbld:3: String is a builtin type, only the Std modules can declare it"]);
+    assert!(Resolver::new(&mman).check_module(&mman.find_module("Std.String").unwrap()).is_empty());
+    // The builtin String keeps the hierarchy that Std.String declares
+    let dispatcher = Dispatcher::new(&mman);
+    assert_eq!(dispatcher.resolve(&Type::String, "hello"), Err(DispatchError::DoesNotUnderstand(Type::String, "hello".to_string())));
+    assert_eq!(dispatcher.resolve(&Type::String, "length").map(|d| d.module), Ok("Std.String".to_string()));
+}
+
+#[test]
+fn test_resolve_import_errors() {
+    let mman = load_sources(&[
+        ("A.kbld", "module A;\nstruct Shared {}\n"),
+        ("B.kbld", "module B;\nstruct Shared {}\n"),
+        ("Missing.kbld", "module Missing;\nimport Std.Nothing;\nuse A (Nope);\n"),
+        ("Listed.kbld", "module Listed;\nuse A (Shared);\nuse B (Shared);\n"),
+        ("Glob.kbld", "module Glob;\nimport A;\nimport B;\nlet x: Shared = 1\n"),
+        ("Local.kbld", "module Local;\nimport A;\nimport B;\nstruct Shared {}\nlet x: Shared = 1\n"),
+    ]);
+    let resolver = Resolver::new(&mman);
+    let kinds = |name: &str| resolver.check_module(&mman.find_module(name).unwrap()).into_iter().map(|e| (e.line, e.kind)).collect::<Vec<_>>();
+
+    assert_eq!(kinds("Missing"), vec![
+        (2, ResolveErrorKind::UnknownModule("Std.Nothing".to_string())),
+        (3, ResolveErrorKind::UnknownName("A".to_string(), "Nope".to_string())),
+    ]);
+    assert_eq!(kinds("Listed"), vec![
+        (3, ResolveErrorKind::Ambiguous("Shared".to_string(), vec!["A".to_string(), "B".to_string()])),
+    ]);
+    assert_eq!(kinds("Glob"), vec![
+        (4, ResolveErrorKind::Ambiguous("Shared".to_string(), vec!["A".to_string(), "B".to_string()])),
+    ]);
+    assert!(kinds("Local").is_empty());
+
+    let e = resolver.check_module(&mman.find_module("Missing").unwrap()).remove(0);
+    assert_eq!(format!("{}", e), "Missing.kbld:2: Unknown module Std.Nothing");
+}
+
+#[test]
+fn test_resolve_exports() {
+    let mman = load_sources(&[
+        ("Lib.kbld", "module Lib (Public);\nstruct Public {}\nstruct Private {}\n"),
+        ("Sealed.kbld", "module Sealed ();\nstruct Hidden {}\n"),
+        ("Open.kbld", "module Open;\nstruct Hidden {}\n"),
+        ("Listed.kbld", "module Listed;\nuse Lib (Public, Private);\n"),
+        ("Glob.kbld", "module Glob;\nimport Lib;\nlet x: Public = 1;\nlet y: Private = 2;\n"),
+        ("Shadow.kbld", "module Shadow;\nimport Sealed;\nimport Open;\nlet x: Hidden = 1\n"),
+    ]);
+    let resolver = Resolver::new(&mman);
+    let kinds = |name: &str| resolver.check_module(&mman.find_module(name).unwrap()).into_iter().map(|e| (e.line, e.kind)).collect::<Vec<_>>();
+
+    assert_eq!(mman.find_module("Lib").unwrap().get_exports(), Some(vec!["Public".to_string()]));
+    assert_eq!(mman.find_module("Sealed").unwrap().get_exports(), Some(vec![]));
+    assert_eq!(mman.find_module("Open").unwrap().get_exports(), None);
+
+    assert_eq!(kinds("Listed"), vec![(2, ResolveErrorKind::NotExported("Lib".to_string(), "Private".to_string()))]);
+    assert_eq!(kinds("Glob"), vec![(4, ResolveErrorKind::NotExported("Lib".to_string(), "Private".to_string()))]);
+    // A private struct does not clash with an exported one of the same name
+    assert!(kinds("Shadow").is_empty());
+
+    let e = resolver.check_module(&mman.find_module("Glob").unwrap()).remove(0);
+    assert_eq!(format!("{}", e), "Glob.kbld:4: Struct Private is private to module Lib, add it to the export list of Lib to use it here");
+}
+
+#[test]
+#[should_panic(expected = "Lib.kbld:1: Module Lib exports Missing, but does not declare it")]
+fn test_unknown_export() {
+    load_sources(&[("Lib.kbld", "module Lib (Missing);\n")]);
+}
+
+#[test]
+fn test_dependency_order() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\nimport Std.String;\nuse Std.Number (Number);\n"),
+        ("String.kbld", "module Std.String;\nimport Std.Number;\n"),
+        ("Number.kbld", "module Std.Number;\nstruct Number {}\n"),
+        ("Other.kbld", "module Other;\n"),
+    ]);
+    let graph = mman.dependency_graph();
+    assert_eq!(graph.get_dependencies("Main"), vec!["Std.Number", "Std.String"]);
+    assert!(graph.find_cycles().is_empty());
+
+    let order = mman.modules_in_order().unwrap().iter().map(|m| m.get_full_name()).collect::<Vec<String>>();
+    assert_eq!(order, vec!["Std.Number", "Std.String", "Main", "Other"]);
+
+    assert_eq!(graph.to_dot(), "digraph modules {\n    \"Main\";\n    \"Main\" -> \"Std.Number\";\n    \"Main\" -> \"Std.String\";\n    \"Other\";\n    \"Std.Number\";\n    \"Std.String\";\n    \"Std.String\" -> \"Std.Number\";\n}\n");
+}
+
+#[test]
+fn test_dependency_cycles() {
+    let mman = load_sources(&[
+        ("A.kbld", "module A;\nimport B;\n"),
+        ("B.kbld", "module B;\nimport C;\n"),
+        ("C.kbld", "module C;\nimport A;\nimport D;\n"),
+        ("D.kbld", "module D;\n"),
+        ("Self.kbld", "module Self;\nimport Self;\n"),
+    ]);
+    let cycles = mman.dependency_graph().find_cycles();
+    assert_eq!(cycles, vec![vec!["A", "B", "C", "A"], vec!["Self", "Self"]]);
+
+    let e = mman.modules_in_order().unwrap_err();
+    assert_eq!(e.cycles, cycles);
+    assert_eq!(format!("{}", e), "Import cycle: A -> B -> C -> A\nImport cycle: Self -> Self");
+}
+
+#[test]
+fn test_duplicate_declarations() {
+    let mman = load_sources(&[
+        ("Dup.kbld", "module Dup;\n\
+            struct Nop {}\n\
+            struct Main {}\n\
+            struct Nop {}\n\
+            message Nop [nop] {}\n\
+            message Nop [nop] -> Nop {}\n\
+            call Nop [nop] {}\n\
+            message Main [nop] {}\n\
+            message Main [x: Integer, y: Integer] {}\n\
+            message Main [y: Integer, x: Integer] {}\n\
+            call Main [x: Integer] {}\n\
+            call Main [x: Float] {}\n\
+            main {}\n\
+            main {}\n"),
+    ]);
+    let m = mman.find_module("Dup").unwrap();
+    let (table, errors) = SymbolTable::collect(&m);
+
+    assert_eq!(table.get_struct("Nop"), Some(2));
+    assert_eq!(table.get_struct("Main"), Some(3));
+    assert_eq!(table.get_message("Nop", "nop"), Some(5));
+    assert_eq!(table.get_call("Nop", "nop"), Some(7));
+    assert_eq!(table.get_message("Main", "x:y:"), Some(9));
+    assert_eq!(table.get_message("Main", "y:x:"), Some(10));
+    assert_eq!(table.get_call("Main", "x:"), Some(11));
+    assert_eq!(table.get_call("Main", "nop"), None);
+    assert_eq!(table.get_main(), Some(13));
+
+    let found = errors.iter().map(|e| (e.kind, e.name.clone(), e.first_line, e.line)).collect::<Vec<_>>();
+    assert_eq!(found, vec![
+        (SymbolKind::Struct, "Nop".to_string(), 2, 4),
+        (SymbolKind::Message, "Nop [nop]".to_string(), 5, 6),
+        (SymbolKind::Call, "Main [x:]".to_string(), 11, 12),
+        (SymbolKind::Main, "block".to_string(), 13, 14),
+    ]);
+    assert_eq!(errors[0].to_string(), "Dup.kbld:4: Duplicate struct Nop, first declared at Dup.kbld:2");
+}
+
+fn type_errors(mman: &ModuleManager, module: &str) -> Vec<(i32, TypeErrorKind)> {
+    let checker = TypeChecker::new(mman);
+    checker.check_module(&mman.find_module(module).unwrap()).into_iter().map(|e| (e.line, e.kind)).collect()
+}
+
+#[test]
+fn test_type_check() {
+    let mman = load_sources(&[
+        ("Geometry.kbld", "module Geometry;\n\
+            struct Point { x: Integer, y: Integer }\n\
+            message Point [x] -> Integer { return this.x }\n"),
+        ("Main.kbld", "module Main;\n\
+            import Geometry;\n\
+            struct Main {}\n\
+            call Main [at: Integer, and: Integer] -> Point {\n\
+                let p: Point = Point {x: at, y: and}\n\
+                return p\n\
+            }\n\
+            message Point [scale: Float] -> Float {\n\
+                if scale > 1 then { return scale * 2 } else { return 0.5 }\n\
+            }\n\
+            message Point [name] -> String { return \"point\" }\n\
+            let a = 3 + 5 * 9 / 54.08\n\
+            let b: Float = a\n\
+            let p: Point = [Main at: 1, and: 2]\n\
+            let s: Float = [p scale: 2.5]\n\
+            let n: String = [p name]\n\
+            let c: Boolean = (1 < 2) == true\n"),
+    ]);
+    assert_eq!(type_errors(&mman, "Main"), vec![]);
+}
+
+#[test]
+fn test_type_errors() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Point { x: Integer, y: Integer }\n\
+            message Point [moveBy: Integer, and: Integer] -> Point { return 3 }\n\
+            message Point [name] { return \"p\" }\n\
+            let a: String = 3\n\
+            let p = Point {x: 1.5, z: 2}\n\
+            let q = Point {x: 1, y: 2}\n\
+            let b = [q moveBy: \"left\", and: 2]\n\
+            let c = [q fly]\n\
+            let d = \"a\" + 1\n\
+            let e = missing\n\
+            let f: Banana = 1\n\
+            message Point [check] -> Integer { if 1 then { return 2 } else { return 3 } }\n\
+            let g = [q name]\n\
+            let h: Integer = g\n"),
+    ]);
+    let t = |s: &str| Type::Instance("Main".to_string(), s.to_string());
+    assert_eq!(type_errors(&mman, "Main"), vec![
+        (3, TypeErrorKind::Mismatch("return".to_string(), t("Point"), Type::Integer)),
+        (4, TypeErrorKind::Mismatch("return".to_string(), Type::Nothing, Type::String)),
+        (5, TypeErrorKind::Mismatch("let a".to_string(), Type::String, Type::Integer)),
+        (6, TypeErrorKind::Mismatch("member x of Point".to_string(), Type::Integer, Type::Float)),
+        (6, TypeErrorKind::UnknownMember("Point".to_string(), "z".to_string())),
+        (6, TypeErrorKind::MissingMember("Point".to_string(), "y".to_string())),
+        (8, TypeErrorKind::Mismatch("argument moveBy: of [moveBy:and:]".to_string(), Type::Integer, Type::String)),
+        (9, TypeErrorKind::DoesNotUnderstand(t("Point"), "fly".to_string())),
+        (10, TypeErrorKind::InvalidOperands(TokenType::Plus, Type::String, Type::Integer)),
+        (11, TypeErrorKind::UnknownVariable("missing".to_string())),
+        (12, TypeErrorKind::Unresolved(ResolveErrorKind::UnknownStruct("Banana".to_string()))),
+        (13, TypeErrorKind::Mismatch("if condition".to_string(), Type::Boolean, Type::Integer)),
+        (15, TypeErrorKind::Mismatch("let h".to_string(), Type::Integer, Type::Nothing)),
+    ]);
+
+    let checker = TypeChecker::new(&mman);
+    let e = checker.check_module(&mman.find_module("Main").unwrap()).remove(0);
+    assert_eq!(e.to_string(), "Main.kbld:3: Type mismatch in return: expected Point, found Integer");
+}
+
+#[test]
+fn test_let_inference() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Box { value: Integer }\n\
+            struct Main {}\n\
+            message(T) Main [identity: T] -> T { return identity }\n\
+            message(T) Main [pick: T, or: T] -> T { let first = pick\nreturn first }\n\
+            message(T) Main [make] -> T {}\n\
+            let m = Main {}\n\
+            let a = 3 + 5 * 9 / 54.08\n\
+            let b = [m identity: \"text\"]\n\
+            let c = [m identity: Box {value: 1}]\n\
+            let d = [m pick: 1, or: 2]\n\
+            let e: Integer = [m identity: 2]\n\
+            let f = [m pick: 1, or: true]\n\
+            let g = [m make]\n\
+            let h = [m identity: Main]\n"),
+    ]);
+    let m = mman.find_module("Main").unwrap();
+    let (table, errors) = TypeChecker::new(&mman).infer_module(&m);
+    assert_eq!(errors.into_iter().map(|e| (e.line, e.kind)).collect::<Vec<_>>(), vec![
+        (14, TypeErrorKind::Mismatch("argument or: of [pick:or:]".to_string(), Type::Integer, Type::Boolean)),
+        (15, TypeErrorKind::CannotInfer("T".to_string(), "make".to_string())),
+    ]);
+
+    let lets = m.get_code().iter().filter_map(|inst| match **inst {
+        Expression::LetStatement { id, ref bound_name, .. } => Some((bound_name.clone(), table.get_let(id))),
+        _ => None
+    }).collect::<Vec<_>>();
+    let t = |s: &str| Type::Instance("Main".to_string(), s.to_string());
+    assert_eq!(lets, vec![
+        ("m".to_string(), Some(t("Main"))),
+        ("a".to_string(), Some(Type::Float)),
+        ("b".to_string(), Some(Type::String)),
+        ("c".to_string(), Some(t("Box"))),
+        ("d".to_string(), Some(Type::Integer)),
+        ("e".to_string(), Some(Type::Integer)),
+        ("f".to_string(), Some(Type::Integer)),
+        ("g".to_string(), None),
+        ("h".to_string(), Some(Type::Class(Box::new(t("Main"))))),
+    ]);
+}
+
+#[test]
+fn test_keyword_order() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Main {}\n\
+            call Main [x: Integer, y: Float] -> Integer {}\n\
+            call Main [y: Float, x: Integer] -> String {}\n\
+            let a: Integer = [Main x: 1, y: 2.5]\n\
+            let b: String = [Main y: 2.5, x: 1]\n\
+            let c = [Main x: 2.5, y: 1]\n"),
+    ]);
+    let m = mman.find_module("Main").unwrap();
+    let decls = m.get_code().iter().filter_map(|inst| match **inst {
+        Expression::MessageDeclaration { ref args_or_name, ref selector, .. } => Some((args_or_name.clone(), selector.clone())),
+        _ => None
+    }).collect::<Vec<_>>();
+    let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+    assert_eq!(decls, vec![
+        (Ok(vec![pair("x", "Integer"), pair("y", "Float")]), "x:y:".to_string()),
+        (Ok(vec![pair("y", "Float"), pair("x", "Integer")]), "y:x:".to_string()),
+    ]);
+
+    // Arguments are bound by position, so each keyword is checked against its own type
+    assert_eq!(type_errors(&mman, "Main"), vec![
+        (7, TypeErrorKind::Mismatch("argument x: of [x:y:]".to_string(), Type::Integer, Type::Float)),
+        (7, TypeErrorKind::Mismatch("argument y: of [x:y:]".to_string(), Type::Float, Type::Integer)),
+    ]);
+}
+
+#[test]
+#[should_panic(expected = "Main.kbld:4: Duplicate keyword x in message Main [...]")]
+fn test_duplicate_keyword() {
+    load_sources(&[("Main.kbld", "module Main;\nstruct Main {}\nmessage Main [x: Integer,\nx: Integer] {}\n")]);
+}
+
+#[test]
+fn test_dispatch() {
+    let mman = load_sources(&[
+        ("Base.kbld", "module Base;\n\
+            struct Animal { name: String }\n\
+            message Animal [speak] -> String { return \"...\" }\n\
+            message Animal [name] -> String { return this.name }\n\
+            call Animal [kingdom] -> String { return \"animalia\" }\n"),
+        ("Main.kbld", "module Main;\n\
+            import Base\n\
+            struct Legs { legs: Integer }\n\
+            message Legs [walk] -> Integer { return this.legs }\n\
+            struct Cat [Animal] <Legs> { lives: Integer }\n\
+            message Cat [speak] -> String { return \"meow\" }\n"),
+    ]);
+    let dispatcher = Dispatcher::new(&mman);
+    let t = |m: &str, s: &str| Type::Instance(m.to_string(), s.to_string());
+    let cat = t("Main", "Cat");
+
+    assert_eq!(dispatcher.hierarchy(&cat), vec![cat.clone(), t("Main", "Legs"), t("Base", "Animal")]);
+    let owner = |recv: &Type, sel: &str| dispatcher.resolve(recv, sel).map(|d| d.owner);
+    assert_eq!(owner(&cat, "speak"), Ok(cat.clone()));
+    assert_eq!(owner(&cat, "walk"), Ok(t("Main", "Legs")));
+    assert_eq!(owner(&cat, "name"), Ok(t("Base", "Animal")));
+    assert_eq!(owner(&Type::Class(Box::new(cat.clone())), "kingdom"), Ok(t("Base", "Animal")));
+    assert_eq!(owner(&t("Base", "Animal"), "walk"), Err(DispatchError::DoesNotUnderstand(t("Base", "Animal"), "walk".to_string())));
+    // Calls are only found on the struct itself, and messages only on its instances
+    assert!(dispatcher.resolve(&cat, "kingdom").is_err());
+    assert_eq!(dispatcher.resolve(&cat, "fly").unwrap_err().to_string(), "Cat does not understand [fly]");
+
+    let members = dispatcher.members(&cat).unwrap().into_iter().map(|m| m.0).collect::<Vec<_>>();
+    assert_eq!(members, vec!["lives", "legs", "name"]);
+    assert!(dispatcher.is_subtype(&cat, &t("Base", "Animal")));
+    assert!(!dispatcher.is_subtype(&cat, &t("Main", "Legs")));
+    assert!(!dispatcher.is_subtype(&t("Base", "Animal"), &cat));
+}
+
+#[test]
+fn test_dispatch_type_check() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Animal { name: String }\n\
+            message Animal [greet: Animal] -> String { return [this name] }\n\
+            message Animal [name] -> String { return this.name }\n\
+            struct Cat [Animal] { lives: Integer }\n\
+            let c = Cat {lives: 9, name: \"Tom\"}\n\
+            let a: Animal = c\n\
+            let s: String = [a greet: c]\n\
+            let n = c.name\n\
+            let l: Cat = a\n\
+            let x = [a lives]\n"),
+    ]);
+    let t = |s: &str| Type::Instance("Main".to_string(), s.to_string());
+    assert_eq!(type_errors(&mman, "Main"), vec![
+        (10, TypeErrorKind::Mismatch("let l".to_string(), t("Cat"), t("Animal"))),
+        (11, TypeErrorKind::DoesNotUnderstand(t("Animal"), "lives".to_string())),
+    ]);
+}
+
+fn run_main(mman: &ModuleManager, module: &str) -> Result<Value, Box<RuntimeError>> {
+    Interpreter::new(mman).run_main(mman.get_module(module).unwrap())
+}
+
+#[test]
+fn test_interpreter() {
+    let mman = load_sources(&[
+        ("Shapes.kbld", "module Shapes;\n\
+            struct Named { name: String }\n\
+            message Named [name] -> String { return this.name }\n\
+            struct Shape <Named> { sides: Integer }\n\
+            message Shape [area] -> Float { return 0.0 }\n\
+            message Shape [describe] -> String { return [name] }\n\
+            struct Square [Shape] { size: Integer }\n\
+            message Square [area] -> Float { return this.size * this.size * 1.0 }\n\
+            call Square [side: Integer] -> Square { return Square {size: side, sides: 4, name: \"square\"} }\n"),
+        ("Main.kbld", "module Main;\n\
+            import Shapes\n\
+            struct Counter { n: Integer }\n\
+            message Counter [fact: Integer] -> Integer {\n\
+                if fact < 2 then { return 1 }\n\
+                return fact * [this fact: fact - 1]\n\
+            }\n\
+            let base = 10\n\
+            main {\n\
+                let s = [Square side: 3]\n\
+                let c = Counter {n: 0}\n\
+                let big = [c fact: 5] > 100\n\
+                if big then { let base = 1 } else { return false }\n\
+                return [s describe] == \"square\" == true\n\
+            }\n"),
+    ]);
+    assert_eq!(run_main(&mman, "Main"), Ok(Value::Boolean(true)));
+
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Point { x: Integer, y: Integer }\n\
+            message Point [plus: Point] -> Point { return Point {x: this.x + plus.x, y: this.y + plus.y} }\n\
+            main {\n\
+                let p = [Point {x: 1, y: 2} plus: Point {x: 10, y: -20}]\n\
+                return p\n\
+            }\n"),
+    ]);
+    let p = run_main(&mman, "Main").unwrap();
+    assert_eq!(p.to_string(), "Point {x: 11, y: -18}");
+    assert_eq!(p.get_type(), Type::Instance("Main".to_string(), "Point".to_string()));
+}
+
+#[test]
+fn test_interpreter_arithmetic() {
+    let value = |expr: &str| {
+        let src = format!("module Main;\nlet a = 7 / 2\nmain {{ return {} }}\n", expr);
+        run_main(&load_sources(&[("Main.kbld", &src)]), "Main")
+    };
+    assert_eq!(value("a"), Ok(Value::Integer(3)));
+    assert_eq!(value("3 + 5 * 9 / 54.08"), Ok(Value::Float(3.0 + 45.0 / 54.08)));
+    assert_eq!(value("-a"), Ok(Value::Integer(-3)));
+    assert_eq!(value("1 < 2.5"), Ok(Value::Boolean(true)));
+    assert_eq!(value("a == 3"), Ok(Value::Boolean(true)));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let error = |src: &str| run_main(&load_sources(&[("Main.kbld", src)]), "Main").unwrap_err();
+    let e = error("module Main;\nlet a = 0\nmain {\nreturn 1 / a\n}\n");
+    assert_eq!((e.line, e.kind.clone()), (4, RuntimeErrorKind::DivisionByZero));
+    assert_eq!(e.to_string(), "Main.kbld:4: Division by zero");
+    let e = error("module Main;\nstruct Nop {}\nmain {\n[Nop {} fly]\n}\n");
+    assert_eq!(e.kind, RuntimeErrorKind::DoesNotUnderstand(Type::Instance("Main".to_string(), "Nop".to_string()), "fly".to_string()));
+    let e = error("module Main;\nlet a = 1\n");
+    assert_eq!(e.to_string(), "Main.kbld: Module Main has no main block");
+}
+
+fn run_vm(mman: &ModuleManager, module: &str) -> Result<Value, Box<RuntimeError>> {
+    let program = Compiler::new(mman).compile(mman.get_module(module).unwrap())?;
+    let result = Vm::new(mman, &program).run();
+    result
+}
+
+#[test]
+fn test_vm() {
+    let sources = [
+        "module Main;\nlet a = 7 / 2\nmain { return a * 2 + -1 }\n",
+        "module Main;\nmain { let a = 1\nif a < 2 then { let a = 5\nreturn a } else { return 0 } }\n",
+        "module Main;\nmain { if false then { return 1 }\nlet x = 2.5\nreturn x == 2.5 }\n",
+        "module Main;\n\
+            struct Animal { name: String }\n\
+            message Animal [name] -> String { return this.name }\n\
+            struct Cat [Animal] { lives: Integer }\n\
+            message Cat [fact: Integer] -> Integer { if fact < 2 then { return 1 }\nreturn fact * [fact: fact - 1] }\n\
+            call Cat [named: String] -> Cat { return Cat {name: named, lives: 9} }\n\
+            main { let c = [Cat named: \"Tom\"]\nif [c name] == \"Tom\" then { return [c fact: c.lives] }\nreturn 0 }\n",
+    ];
+    for src in sources.iter() {
+        let mman = load_sources(&[("Main.kbld", src)]);
+        let expected = run_main(&mman, "Main");
+        assert!(expected.is_ok(), "{:?}", expected);
+        assert_eq!(run_vm(&mman, "Main"), expected, "{}", src);
+    }
+}
+
+#[test]
+fn test_vm_errors() {
+    let mman = load_sources(&[("Main.kbld", "module Main;\nstruct Nop {}\nmain {\nlet a = 0\nreturn 1 /\na\n}\n")]);
+    let e = run_vm(&mman, "Main").unwrap_err();
+    assert_eq!((e.line, e.kind.clone()), (5, RuntimeErrorKind::DivisionByZero));
+    let mman = load_sources(&[("Main.kbld", "module Main;\nstruct Nop {}\nmain {\n[Nop {} fly]\n}\n")]);
+    assert_eq!(run_vm(&mman, "Main"), run_main(&mman, "Main"));
+    let mman = load_sources(&[("Main.kbld", "module Main;\nmain {\nreturn missing\n}\n")]);
+    let e = Compiler::new(&mman).compile(mman.get_module("Main").unwrap()).unwrap_err();
+    assert_eq!(e.to_string(), "Main.kbld:3: Unknown variable missing");
+}
+
+#[test]
+fn test_vm_bytecode() {
+    let mman = load_sources(&[
+        ("Main.kbld", "module Main;\n\
+            struct Point { x: Integer, y: Integer }\n\
+            message Point [x] -> Integer { return this.x }\n\
+            main {\n\
+                let p = Point {y: 2, x: 1}\n\
+                return [p x] + [p x]\n\
+            }\n"),
+    ]);
+    let program = Compiler::new(&mman).compile(mman.get_module("Main").unwrap()).unwrap();
+    let main = &program.functions[program.main.unwrap()];
+    assert_eq!(main.chunk.constants, vec![
+        Constant::Value(Value::Integer(2)),
+        Constant::Value(Value::Integer(1)),
+        Constant::Layout(Type::Instance("Main".to_string(), "Point".to_string()), vec!["y".to_string(), "x".to_string()]),
+        Constant::Name("x".to_string()),
+    ]);
+    assert_eq!(main.chunk.code[0], Opcode::Constant as u8);
+    assert_eq!(Opcode::from_byte(Opcode::Return as u8), Some(Opcode::Return));
+    assert_eq!(Opcode::from_byte(200), None);
+
+    // Each send fills its own cache with the receiver type and the function it ran
+    assert_eq!(Vm::new(&mman, &program).run(), Ok(Value::Integer(2)));
+    let point = Type::Instance("Main".to_string(), "Point".to_string());
+    let message = *program.declarations.get(&("Main".to_string(), 1)).unwrap();
+    for cache in main.chunk.caches.iter() {
+        assert_eq!(*cache.borrow(), Some((point.clone(), Target::Function(message))));
+    }
+
+    let text = disassemble(main);
+    assert!(text.starts_with("== main (0 argument(s), 2 slot(s)) ==\n0000    5 Constant 0 (Integer(2))\n"), "{}", text);
+    assert!(text.contains("    6 GetLocal 1\n"), "{}", text);
+    assert!(text.contains("| Send 3 (x) 0 cache 1 (Point -> function 0)\n"), "{}", text);
+}
+
+#[test]
+fn test_library_roundtrip() {
+    let mman = load_sources(&[
+        ("Shapes.kbld", "module Shapes (Square);\n\
+            import Other\n\
+            use Std.Number (Number)\n\
+            struct Named { name: String }\n\
+            struct Square [Shape] <Named> { size: Integer }\n\
+            message(T) Square [pick: T, or: T] -> T { return or }\n\
+            call Square [side: Integer] -> Square { return Square {size: -side, name: \"s\"} }\n\
+            message Square [area] -> Float { if this.size > 1 == true then { return this.size * 1.5 / 2 } else { return [this side] } }\n\
+            message Square [corners: Map(String, Integer[])] -> Integer[] { return {1, 2, this.size} }\n\
+            let x: Integer = 3 + 4 < 5\n\
+            main { let s = [Square side: 3]\nreturn [s area] }\n"),
+    ]);
+    let m = mman.find_module("Shapes").unwrap();
+    let lib = Library::from_module(&m);
+    let mut bytes = vec![];
+    lib.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..6], b"KLB\0\x02\x00");
+
+    let read = Library::read("Shapes.klb", &mut &bytes[..]).unwrap();
+    assert_eq!(read.name, "Shapes");
+    assert_eq!(read.source, "Shapes.kbld");
+    assert!(!read.script);
+    assert_eq!(read.exports, Some(vec!["Square".to_string()]));
+    assert_eq!(read.structs[1], StructLayout {
+        name: "Square".to_string(),
+        parent: Some("Shape".to_string()),
+        composers: vec!["Named".to_string()],
+        members: vec![("size".to_string(), "Integer".to_string())],
+    });
+    assert_eq!(read.messages.iter().map(|msg| (msg.is_call, msg.selector.clone(), msg.index)).collect::<Vec<_>>(), vec![
+        (false, "pick:or:".to_string(), 4), (true, "side:".to_string(), 5), (false, "area".to_string(), 6),
+        (false, "corners:".to_string(), 7),
+    ]);
+    assert_eq!(read.messages[3].args, vec!["Map(String, Integer[])".to_string()]);
+    assert_eq!(read.messages[0].args, vec!["T".to_string(), "T".to_string()]);
+    assert_eq!(format!("{:?}", read.code), format!("{:?}", m.get_code()));
+
+    let mut mman = ModuleManager::new();
+    assert_eq!(load_library("Shapes.klb", &bytes[..], &mut mman), Ok("Shapes".to_string()));
+    let loaded = mman.find_module("Shapes").unwrap();
+    assert_eq!(loaded.get_file_name(), "Shapes.kbld");
+    assert!(!loaded.exports_struct("Named"));
+}
+
+#[test]
+fn test_library_errors() {
+    let mman = load_sources(&[("Main.kbld", "module Main;\nmain { return 1 }\n")]);
+    let mut bytes = vec![];
+    Library::from_module(&mman.find_module("Main").unwrap()).write(&mut bytes).unwrap();
+    let read = |b: &[u8]| Library::read("Main.klb", &mut &b[..]).map(|_| ()).map_err(|e| e.kind);
+
+    assert_eq!(read(&bytes), Ok(()));
+    assert_eq!(read(b"module Main;"), Err(LibraryErrorKind::NotALibrary));
+    let mut newer = bytes.clone();
+    newer[4] = 3;
+    assert_eq!(read(&newer), Err(LibraryErrorKind::IncompatibleVersion(3)));
+    assert_eq!(Library::read("Main.klb", &mut &newer[..]).unwrap_err().to_string(),
+               "Main.klb: Library format version 3 is not supported, expected version 2");
+    let mut older = bytes.clone();
+    older[4] = 1;
+    assert_eq!(read(&older), Err(LibraryErrorKind::IncompatibleVersion(1)));
+    assert_eq!(read(&bytes[..bytes.len() - 1]), Err(LibraryErrorKind::Corrupt("line".to_string())));
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert_eq!(read(&longer), Err(LibraryErrorKind::Corrupt("end of library".to_string())));
+}
+
+#[test]
+fn test_load_not_a_library() {
+    let e = load_library("Main.klb", &b"module Main;"[..], &mut ModuleManager::new()).unwrap_err();
+    assert_eq!(e.to_string(), "Main.klb: Not a Kobold library");
+
+    // A library built from a module that is loaded from its source
+    let mut mman = load_sources(&[("Main.kbld", "module Main;\nmain { return 1 }\n")]);
+    let mut bytes = vec![];
+    Library::from_module(mman.get_module("Main").unwrap()).write(&mut bytes).unwrap();
+    assert_eq!(load_library("Main.klb", &bytes[..], &mut mman).unwrap_err().kind, LibraryErrorKind::NameTaken("Main".to_string()));
+}
+
+#[test]
+fn test_script() {
+    let system = ("system.kbld", include_str!("../../std/system.kbld"));
+    let mut mman = load_sources(&[system]);
+    load_script("scripts/hello.ksc", &b"use Std.System (System)\n\
+        struct Point { x: Integer, y: Integer }\n\
+        message Point [sum] -> Integer { return this.x + this.y }\n\
+        let p = Point { x: 3, y: 4 }\n\
+        let s = [p sum]\n\
+        if [System isScript] then { return s * 10 }\n\
+        return s\n"[..], &mut mman).unwrap();
+    let script = mman.get_module("hello").unwrap();
+    assert!(script.is_script());
+    assert_eq!(script.get_file_name(), "scripts/hello.ksc");
+    assert_eq!(run_main(&mman, "hello"), Ok(Value::Integer(70)));
+    assert_eq!(run_vm(&mman, "hello"), Ok(Value::Integer(70)));
+
+    // A script stays one once built into a library
+    let mut bytes = vec![];
+    Library::from_module(script).write(&mut bytes).unwrap();
+    let mut built = load_sources(&[system]);
+    load_library("hello.klb", &bytes[..], &mut built).unwrap();
+    assert!(built.get_module("hello").unwrap().is_script());
+    assert_eq!(run_main(&built, "hello"), Ok(Value::Integer(70)));
+
+    let mman = load_sources(&[system, ("Main.kbld", "module Main;\nuse Std.System (System)\nmain { return [System isScript] }\n")]);
+    assert_eq!(run_main(&mman, "Main"), Ok(Value::Boolean(false)));
+    assert_eq!(run_vm(&mman, "Main"), Ok(Value::Boolean(false)));
+
+    // Outside of messages, a shorthand send is a call of Std.System, which needs no import
+    let mut mman = load_sources(&[system, ("Main.kbld", "module Main;\nmain { return [isScript] }\n")]);
+    load_script("short.ksc", &b"if [isScript] then { return 1 }\nreturn 2\n"[..], &mut mman).unwrap();
+    assert!(type_errors(&mman, "Main").is_empty());
+    assert!(type_errors(&mman, "short").is_empty());
+    assert_eq!(run_main(&mman, "Main"), Ok(Value::Boolean(false)));
+    assert_eq!(run_vm(&mman, "Main"), Ok(Value::Boolean(false)));
+    assert_eq!(run_main(&mman, "short"), Ok(Value::Integer(1)));
+    assert_eq!(run_vm(&mman, "short"), Ok(Value::Integer(1)));
+    let mman = load_sources(&[system, ("Main.kbld", "module Main;\nmain { return [nope] }\n")]);
+    assert_eq!(type_errors(&mman, "Main"), vec![(2, TypeErrorKind::NoReceiver("nope".to_string()))]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().kind, RuntimeErrorKind::NoReceiver("nope".to_string()));
+}
+
+#[test]
+fn test_script_module_declaration() {
+    let e = load_script("hello.ksc", &b"let a = 1\nmodule Hello;\n"[..], &mut ModuleManager::new()).unwrap_err();
+    assert_eq!(e.to_string(), "hello.ksc:2: Scripts cannot declare 'module'");
+    assert_eq!(script_name("scripts/hello.ksc"), Some("hello".to_string()));
+    assert_eq!(script_name(".."), None);
+    let e = load_script("..", &b"return 1\n"[..], &mut ModuleManager::new()).unwrap_err();
+    assert_eq!(e.kind, SyntaxErrorKind::NotAScript);
+}
+
+#[test]
+fn test_repl() {
+    let mut mman = load_sources(&[("Shapes.kbld", "module Shapes;\nstruct Square { side: Integer }\n\
+        message Square [area] -> Integer { return this.side * this.side }\n")]);
+    let mut r = Repl::new(&mut mman);
+    assert_eq!(r.eval(&mut mman, "struct Point { x: Integer, y: Integer }\nmessage Point [sum] -> Integer { return this.x + this.y }"), Ok("".to_string()));
+    assert_eq!(r.eval(&mut mman, "let p = Point { x: 3, y: 4 }"), Ok("".to_string()));
+    assert_eq!(r.eval(&mut mman, "[p sum] * 2"), Ok("14".to_string()));
+    assert_eq!(r.eval(&mut mman, "3 + 5 * 9 / 54.08"), Ok("3.8321005917159763".to_string()));
+    assert_eq!(r.eval(&mut mman, ":type p"), Ok("Point".to_string()));
+    assert_eq!(r.eval(&mut mman, ":type p.x < 2"), Ok("Boolean".to_string()));
+    assert!(r.eval(&mut mman, ":ast 1 + 2").unwrap().starts_with("BinaryExpression("));
+
+    // Errors leave the session as it was
+    assert_eq!(r.eval(&mut mman, "[p area]"), Err("<repl>:1: Point does not understand [area]".to_string()));
+    assert!(r.eval(&mut mman, "struct Point {}").is_err());
+    assert_eq!(r.eval(&mut mman, "Square { side: 3 }").unwrap_err(), "<repl>:1: Unknown struct Square");
+    assert_eq!(r.eval(&mut mman, ":load Nowhere"), Err("Unknown module Nowhere".to_string()));
+    assert_eq!(r.eval(&mut mman, ":load Shapes"), Ok("Loaded Shapes".to_string()));
+    assert_eq!(r.eval(&mut mman, "let s = Square { side: 3 }\n[s area] + [p sum]"), Ok("16".to_string()));
+    assert_eq!(r.eval(&mut mman, ":quux"), Err("Unknown command :quux".to_string()));
+}
+
+#[test]
+fn test_repl_input() {
+    assert!(is_open("message Point [sum] -> Integer {"));
+    assert!(is_open("[[p sum]"));
+    assert!(!is_open("let s = \"{[\" # ]]"));
+    assert!(!is_open("if x then { return 1 }\n"));
+
+    let mut mman = ModuleManager::new();
+    let mut out = vec![];
+    repl::run(&mut mman, &b"struct Box {\nv: Integer\n}\n\n# Nothing to print\nlet b = Box { v: 3 }\nb.v * 5\n:quit\n1\n"[..], &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "> ... ... > > > > 15\n> ");
+}
+
+#[test]
+fn test_number() {
+    let number = ("number.kbld", include_str!("../../std/number.kbld"));
+    let checks = [
+        ("[7 plus: 5] * [2 negated]", Value::Integer(-24)),
+        ("[7 dividedBy: 2] + [-7 modulo: 3]", Value::Integer(2)),
+        ("[2.5 plus: 1]", Value::Float(3.5)),
+        ("[[3 max: 9] min: 4]", Value::Integer(4)),
+        ("[1.5 max: 2]", Value::Float(2.0)),
+        ("[[-2.5 abs] floor] + [2.5 round] + [-0.5 ceiling]", Value::Integer(5)),
+        ("[9 toFloat] / 2", Value::Float(4.5)),
+        ("[-7.9 toInteger]", Value::Integer(-7)),
+        ("[[-3 sign] toString]", Value::String("-1".to_string())),
+        ("[2 lessThan: 2.5] == [3 equals: 3.5]", Value::Boolean(false)),
+        ("[16.0 sqrt]", Value::Float(4.0)),
+        ("3 + 5 * 9 / 54.08", Value::Float(3.8321005917159763)),
+    ];
+    for &(expr, ref expected) in checks.iter() {
+        let src = format!("module Main;\nmain {{ return {} }}\n", expr);
+        let mman = load_sources(&[number, ("Main.kbld", &src)]);
+        let errors = TypeChecker::new(&mman).check_module(mman.get_module("Main").unwrap());
+        assert!(errors.is_empty(), "{}: {:?}", expr, errors);
+        assert_eq!(run_main(&mman, "Main").as_ref(), Ok(expected), "{}", expr);
+        assert_eq!(run_vm(&mman, "Main").as_ref(), Ok(expected), "{}", expr);
+    }
+
+    // Integer and Float are numbers, but are not instantiable
+    let mman = load_sources(&[number, ("Main.kbld", "module Main;\nuse Std.Number (Number)\n\
+        message Number [twice] -> Number { return [this times: 2] }\n\
+        main { let n: Number = 3\nlet d: Decimal = 2.5\nreturn Integer {} }\n")]);
+    let checker = TypeChecker::new(&mman);
+    let errors = checker.check_module(mman.get_module("Main").unwrap());
+    assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec![
+        "Main.kbld:3: Number does not understand [times:]".to_string(),
+        "Main.kbld:6: Integer is not a struct, and cannot be instanced".to_string(),
+    ]);
+    let mman = load_sources(&[number, ("Main.kbld", "module Main;\nmain { return [[10000000000.0 times: 10000000000] floor] }\n")]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().kind, RuntimeErrorKind::InvalidNumber("1e20".to_string()));
+}
+
+#[test]
+fn test_string() {
+    let string = ("string.kbld", include_str!("../../std/string.kbld"));
+    let checks = [
+        ("[\"héllo wörld\" length]", Value::Integer(11)),
+        ("[\"héllo\" at: 1]", Value::String("é".to_string())),
+        ("[\"Kob\" concat: \"old\"] + \"!\"", Value::String("Kobold!".to_string())),
+        ("[String slice: \"héllo wörld\", from: 6, to: 11]", Value::String("wörld".to_string())),
+        ("[String slice: \"abc\", from: 1, to: 1]", Value::String("".to_string())),
+        ("[\"a/b\" length]", Value::Integer(3)),
+        ("[\"a/b/c\" split: \"/\"]", Value::Array(Rc::new(vec![Value::String("a".to_string()), Value::String("b".to_string()), Value::String("c".to_string())]))),
+        ("[\"héllo\" contains: \"éll\"]", Value::Boolean(true)),
+        ("[\"ab\" at: 0] == \"a\"", Value::Boolean(true)),
+    ];
+    for &(expr, ref expected) in checks.iter() {
+        let src = format!("module Main;\nmain {{ return {} }}\n", expr);
+        let mman = load_sources(&[string, ("Main.kbld", &src)]);
+        let errors = TypeChecker::new(&mman).check_module(mman.get_module("Main").unwrap());
+        assert!(errors.is_empty(), "{}: {:?}", expr, errors);
+        assert_eq!(run_main(&mman, "Main").as_ref(), Ok(expected), "{}", expr);
+        assert_eq!(run_vm(&mman, "Main").as_ref(), Ok(expected), "{}", expr);
+    }
+
+    let mman = load_sources(&[string, ("Main.kbld", "module Main;\nmain {\nreturn [\"héllo\" at: 5]\n}\n")]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().to_string(), "Main.kbld:3: Index 5 is out of range for length 5");
+    assert_eq!(run_vm(&mman, "Main"), run_main(&mman, "Main"));
+    let mman = load_sources(&[string, ("Main.kbld", "module Main;\nmain { return [String slice: \"abc\", from: 2, to: 1] }\n")]);
+    assert_eq!(run_main(&mman, "Main").map(|v| v.to_string()), Ok("".to_string()));
+    let mman = load_sources(&[string, ("Main.kbld", "module Main;\nmain { return \"a\" + 1 }\n")]);
+    let errors = TypeChecker::new(&mman).check_module(mman.get_module("Main").unwrap());
+    assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec!["Main.kbld:2: Cannot apply Plus to String and Integer".to_string()]);
+}
+
+#[test]
+fn test_array_types() {
+    let mman = load_sources(&[("Main.kbld", "module Main;\n\
+        struct Bag { items: String[], counts: Map(String, Integer), seen: Set(Integer[]) }\n\
+        message Bag [first] -> String { return [this.items at: 0] }\n\
+        main { let a: Integer[] = {1, 2, 3}\nlet e = {}\nlet b = {1, \"two\"}\nreturn a }\n")]);
+    let m = mman.get_module("Main").unwrap();
+    let members = Dispatcher::new(&mman).members(&Type::Instance("Main".to_string(), "Bag".to_string())).unwrap();
+    assert_eq!(members.iter().map(|m| m.1.clone().unwrap().to_string()).collect::<Vec<_>>(), vec!["String[]", "Map(String, Integer)", "Set(Integer[])"]);
+    assert_eq!(type_errors(&mman, "Main"), vec![
+        (3, TypeErrorKind::DoesNotUnderstand(Type::Array(Box::new(Type::String)), "at:".to_string())),
+        (6, TypeErrorKind::Mismatch("array element".to_string(), Type::Integer, Type::String)),
+    ]);
+    assert!(Resolver::new(&mman).check_module(m).is_empty());
+}
+
+#[test]
+fn test_collection() {
+    let collection = ("collection.kbld", include_str!("../../std/collection.kbld"));
+    let string = ("string.kbld", include_str!("../../std/string.kbld"));
+    let number = ("number.kbld", include_str!("../../std/number.kbld"));
+    let checks = [
+        ("{1, 2, 3}", "{1, 2, 3}"),
+        ("[{1, 2, 3} at: 1] + [{} length]", "2"),
+        ("[[{1, 2} with: 3] concat: {4}]", "{1, 2, 3, 4}"),
+        ("[{\"a\"} contains: \"b\"] == [{} isEmpty]", "false"),
+        ("[{1, 2, 3} map: Doubler {}]", "{2, 4, 6}"),
+        ("[Sum {} of: {1, 2, 3}]", "10"),
+        ("[{3, 1, 3} toSet]", "Set {3, 1}"),
+        ("[[[{1, 2} toSet] with: 2] toArray]", "{1, 2}"),
+        ("[[Map keys: {\"a\", \"b\"}, values: {1, 2}] at: \"b\"]", "2"),
+        ("[[Map keys: {\"a\"}, values: {1}] at: \"b\", put: 5]", "Map {a -> 1, b -> 5}"),
+        ("[[[Map keys: {\"a\"}, values: {1}] at: \"a\", put: 5] values]", "{5}"),
+        ("[[Map keys: {1}, values: {true}] containsKey: 2]", "false"),
+        ("[Sum {} ofMap: [Map keys: {\"x\", \"y\"}, values: {4, 5}]]", "9"),
+        ("[\"a,b,,c\" split: \",\"]", "{a, b, , c}"),
+        ("[[\"héllo\" split: \"\"] length]", "5"),
+    ];
+    for &(expr, expected) in checks.iter() {
+        let src = format!("module Main;\n\
+            struct Doubler {{}}\n\
+            message Doubler [value: Integer] -> Integer {{ return value * 2 }}\n\
+            struct Sum {{}}\n\
+            message Sum [of: Integer[]] -> Integer {{ return [[{{0}} concat: [of map: Doubler {{}}]] length] + [[of map: Doubler {{}}] at: 2] }}\n\
+            message Sum [ofMap: Map(String, Integer)] -> Integer {{ return [[ofMap values] at: 0] + [[ofMap values] at: 1] }}\n\
+            main {{ return {} }}\n", expr);
+        let mman = load_sources(&[collection, string, number, ("Main.kbld", &src)]);
+        let errors = TypeChecker::new(&mman).check_module(mman.get_module("Main").unwrap());
+        assert!(errors.is_empty(), "{}: {:?}", expr, errors);
+        assert_eq!(run_main(&mman, "Main").map(|v| v.to_string()), Ok(expected.to_string()), "{}", expr);
+        assert_eq!(run_vm(&mman, "Main").map(|v| v.to_string()), Ok(expected.to_string()), "{}", expr);
+    }
+
+    // The messages sent by each: run on both engines, and their errors keep where they happened
+    let mman = load_sources(&[collection, ("Main.kbld", "module Main;\n\
+        struct Checker {}\n\
+        message Checker [value: Integer] -> Integer {\nreturn 10 / value\n}\n\
+        main {\nlet x = [{1, 0} each: Checker {}]\nreturn [{1} at: 3]\n}\n")]);
+    assert!(type_errors(&mman, "Main").is_empty());
+    assert_eq!(run_main(&mman, "Main").unwrap_err().to_string(), "Main.kbld:4: Division by zero");
+    assert_eq!(run_vm(&mman, "Main"), run_main(&mman, "Main"));
+    let mman = load_sources(&[collection, ("Main.kbld", "module Main;\nmain {\nreturn [[Map keys: {1}, values: {2}] at: 3]\n}\n")]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().to_string(), "Main.kbld:3: No value for the key 3 in the map");
+    assert_eq!(run_vm(&mman, "Main"), run_main(&mman, "Main"));
+    let mman = load_sources(&[collection, ("Main.kbld", "module Main;\nmain { return [{1, 2} at: \"a\"] }\n")]);
+    assert!(type_errors(&mman, "Std.Collection").is_empty());
+    assert_eq!(type_errors(&mman, "Main"), vec![(2, TypeErrorKind::Mismatch("argument at: of [at:]".to_string(), Type::Integer, Type::String))]);
+}
+
+// An Io in memory: what is printed, the lines to read, and the files by path. Clones share them.
+#[derive(Clone, Default)]
+struct MemoryIo {
+    output: Rc<RefCell<String>>,
+    input: Rc<RefCell<VecDeque<String>>>,
+    files: Rc<RefCell<HashMap<String, String>>>,
+}
+
+struct MemoryFile {
+    path: String,
+    files: Rc<RefCell<HashMap<String, String>>>,
+    at: usize, // Where reading goes on from
+}
+
+impl Io for MemoryIo {
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        self.output.borrow_mut().push_str(text);
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(self.input.borrow_mut().pop_front())
+    }
+
+    fn open(&mut self, path: &str) -> io::Result<Box<dyn Stream>> {
+        if path.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No such file"))
+        }
+        self.files.borrow_mut().entry(path.to_string()).or_default();
+        Ok(Box::new(MemoryFile { path: path.to_string(), files: self.files.clone(), at: 0 }))
+    }
+}
+
+impl Stream for MemoryFile {
+    fn read(&mut self) -> io::Result<String> {
+        let text = self.files.borrow()[&self.path][self.at..].to_string();
+        self.at += text.len();
+        Ok(text)
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.files.borrow_mut().get_mut(&self.path).unwrap().push_str(text);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_io() {
+    let io = ("io.kbld", include_str!("../../std/io.kbld"));
+    let mman = load_sources(&[io, ("Main.kbld", "module Main;\nuse Std.IO (IO)\n\
+        main {\n\
+        [IO print: \"Name? \"]\n\
+        let name = [IO readLine]\n\
+        [IO println: \"Hello, \" + name]\n\
+        let f = [IO open: \"notes.txt\"]\n\
+        [f write: 42]\n\
+        [f write: \" apples\"]\n\
+        let text = [f read]\n\
+        [f close]\n\
+        [IO println: [IO readLine] + \"|\" + text]\n\
+        return [[IO open: \"notes.txt\"] read]\n}\n")]);
+    assert!(type_errors(&mman, "Main").is_empty());
+    let program = Compiler::new(&mman).compile(mman.get_module("Main").unwrap()).unwrap();
+    for engine in 0..2 {
+        let memory = MemoryIo::default();
+        memory.input.borrow_mut().push_back("Kobold".to_string());
+        let result = match engine {
+            0 => Interpreter::new(&mman).with_io(Box::new(memory.clone())).run_main(mman.get_module("Main").unwrap()),
+            _ => Vm::new(&mman, &program).with_io(Box::new(memory.clone())).run()
+        };
+        assert_eq!(result, Ok(Value::String("42 apples".to_string())));
+        assert_eq!(*memory.output.borrow(), "Name? Hello, Kobold\n|42 apples\n");
+        assert_eq!(memory.files.borrow()["notes.txt"], "42 apples");
+    }
+
+    let mman = load_sources(&[io, ("Main.kbld", "module Main;\nuse Std.IO (IO)\nmain {\nlet f = [IO open: \"a\"]\n[f close]\nreturn [f read]\n}\n")]);
+    let result = Interpreter::new(&mman).with_io(Box::new(MemoryIo::default())).run_main(mman.get_module("Main").unwrap());
+    assert_eq!(result.unwrap_err().to_string(), "Main.kbld:6: IO error: The file is closed");
+    let mman = load_sources(&[io, ("Main.kbld", "module Main;\nuse Std.IO (IO)\nmain { return [IO open: \"\"] }\n")]);
+    let result = Interpreter::new(&mman).with_io(Box::new(MemoryIo::default())).run_main(mman.get_module("Main").unwrap());
+    assert_eq!(result.unwrap_err().kind, RuntimeErrorKind::Io("No such file".to_string()));
+}
+
+#[test]
+fn test_system() {
+    let system = ("system.kbld", include_str!("../../std/system.kbld"));
+    let collection = ("collection.kbld", include_str!("../../std/collection.kbld"));
+    env::set_var("KOBOLD_TEST_SYSTEM", "set");
+    let mman = load_sources(&[system, collection, ("Main.kbld", "module Main;\nuse Std.System (System)\n\
+        main {\n\
+        if [System time] < 1000000000.0 then { return \"no time\" }\n\
+        if [System isScript] then { return \"script\" }\n\
+        return [[System args] at: 1] + [System env: \"KOBOLD_TEST_SYSTEM\"] + [System env: \"KOBOLD_TEST_UNSET\"]\n}\n")]);
+    assert!(type_errors(&mman, "Main").is_empty());
+    let args = vec!["a".to_string(), "b".to_string()];
+    let program = Compiler::new(&mman).compile(mman.get_module("Main").unwrap()).unwrap();
+    let expected = Ok(Value::String("bset".to_string()));
+    assert_eq!(Interpreter::new(&mman).with_args(args.clone()).run_main(mman.get_module("Main").unwrap()), expected);
+    assert_eq!(Vm::new(&mman, &program).with_args(args).run(), expected);
+
+    let mman = load_sources(&[system, ("Main.kbld", "module Main;\nuse Std.System (System)\nmain {\n[System exit: 3]\nreturn 1\n}\n")]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().kind, RuntimeErrorKind::Exit(3));
+    assert_eq!(run_vm(&mman, "Main").unwrap_err().kind, RuntimeErrorKind::Exit(3));
+    let mman = load_sources(&[system, ("Main.kbld", "module Main;\nuse Std.System (System)\nmain { return [System exit: 4294967296] }\n")]);
+    assert_eq!(run_main(&mman, "Main").unwrap_err().to_string(), "Main.kbld:3: Exit code 4294967296 is out of range");
+    assert_eq!(run_vm(&mman, "Main").unwrap_err().kind, RuntimeErrorKind::InvalidExitCode(4294967296));
+}
+
+#[test]
+fn test_runtime() {
+    let mut runtime = Runtime::default();
+    assert_eq!(runtime.load_module("module Host;\n\
+        struct Clock {}\n\
+        call Clock [now] -> Integer {}\n\
+        call Clock [twice: Integer] -> Integer {}\n\
+        struct Greeter {}\n\
+        call Greeter [greet: String] -> String { return \"Hello, \" + greet + [[Clock twice: [Clock now]] toString] }\n"), Ok("Host".to_string()));
+    assert_eq!(runtime.register_native("Host.Clock", "now", |_, _| Ok(Value::Integer(21))), Ok(()));
+    assert_eq!(runtime.register_native("Clock", "twice:", |ctx, args| ctx.send(args[0].clone(), "plus:", vec![args[0].clone()])), Ok(()));
+    assert_eq!(runtime.register_native("String", "length", |ctx, _| match *ctx.receiver() {
+        Value::String(ref s) => Ok(Value::Integer(s.len() as i64)),
+        _ => Ok(Value::Nothing)
+    }), Ok(()));
+    // A native for a struct that is not loaded would never be called
+    assert_eq!(runtime.register_native("Watch", "now", |_, _| Ok(Value::Nothing)), Err("Unknown struct Watch".to_string()));
+    assert_eq!(runtime.register_native("Host.Watch", "now", |_, _| Ok(Value::Nothing)), Err("Unknown struct Host.Watch".to_string()));
+    assert_eq!(runtime.register_native("Host.Clock", "later", |_, _| Ok(Value::Nothing)), Err("Host.Clock declares no message [later]".to_string()));
+    assert_eq!(runtime.register_native("Greeter", "now", |_, _| Ok(Value::Nothing)), Err("Greeter declares no message [now]".to_string()));
+
+    let greeter = runtime.class("Host", "Greeter").unwrap();
+    assert_eq!(runtime.send(greeter.clone(), "greet:", vec![Value::String("Kobold".to_string())]), Ok(Value::String("Hello, Kobold42".to_string())));
+    // Bytes instead of the characters of Std.String
+    assert_eq!(runtime.send(Value::String("é".to_string()), "length", vec![]), Ok(Value::Integer(2)));
+    assert_eq!(runtime.send(greeter, "wave", vec![]).unwrap_err().to_string(), "<host>: Greeter class does not understand [wave]");
+    assert!(runtime.class("Host", "Missing").is_none());
+
+    assert_eq!(runtime.load_module("module Host;\n"), Err(vec!["<embedded>:1: Name already taken: Host".to_string()]));
+    assert_eq!(runtime.load_module("module Bad;\nmain { return [1 }\n"),
+        Err(vec!["<embedded>:2: Token type mismatch: Identifier expected, RBrace received".to_string()]));
+    assert_eq!(runtime.load_module("module Bad;\nmain { return [1 nope] }\n"), Err(vec!["<embedded>:2: Integer does not understand [nope]".to_string()]));
+    assert!(runtime.class("Bad", "Bad").is_none());
+    assert_eq!(runtime.load_module("module Bad;\nmain { return 1 }\n"), Ok("Bad".to_string()));
+    // The messages of a module loaded after the first sends are found
+    assert_eq!(runtime.load_module("module Later;\nstruct Later {}\ncall Later [answer] -> Integer { return 42 }\n"), Ok("Later".to_string()));
+    assert_eq!(runtime.send(runtime.class("Later", "Later").unwrap(), "answer", vec![]), Ok(Value::Integer(42)));
+}
+
+#[test]
+fn test_stack_overflow() {
+    let source = "module Deep;\nstruct R {}\ncall R [go: Integer] -> Integer { return [R go: go + 1] }\n\
+        call R [down: Integer] -> Integer { if down == 0 then { return 0 } else { return [R down: down - 1] } }\n\
+        main { return [R go: 0] }\n";
+    // The stack of a main thread, which tests do not run on
+    let deep = thread::Builder::new().stack_size(8 << 20).spawn(move || {
+        let mman = load_sources(&[("Deep.kbld", source)]);
+        let e = run_main(&mman, "Deep").unwrap_err();
+        assert_eq!((e.line, e.kind), (3, RuntimeErrorKind::StackOverflow));
+        assert_eq!(run_vm(&mman, "Deep").unwrap_err().kind, RuntimeErrorKind::StackOverflow);
+
+        // A host that sends the message gets the error, instead of its process ending
+        let mut runtime = Runtime::new();
+        assert_eq!(runtime.load_module(source), Ok("Deep".to_string()));
+        let r = runtime.class("Deep", "R").unwrap();
+        let e = runtime.send(r.clone(), "go:", vec![Value::Integer(0)]).unwrap_err();
+        assert_eq!(e.to_string(), format!("<embedded>:3: Stack overflow, more than {} messages deep", MAX_DEPTH));
+        // Just under the limit is fine
+        assert_eq!(runtime.send(r, "down:", vec![Value::Integer(MAX_DEPTH as i64 - 1)]), Ok(Value::Integer(0)));
+    });
+    deep.unwrap().join().unwrap();
+}
+
+#[test]
+fn test_user_operators() {
+    let number = ("number.kbld", include_str!("../../std/number.kbld"));
+    let mman = load_sources(&[number, ("Main.kbld", "module Main;\n\
+        operator infix % 3 left -> selector modulo:\n\
+        operator infix </> 1 right -> selector join:\n\
+        struct Path { text: String }\n\
+        message Path [join: Path] -> Path { return Path { text: \"(\" + this.text + \"-\" + join.text + \")\" } }\n\
+        message Path [depth] -> Integer { return 17 % 5 * 2 + 1 }\n\
+        main {\n\
+        let p = Path { text: \"a\" } </> Path { text: \"b\" } </> Path { text: \"c\" }\n\
+        return p.text + [[p depth] toString]\n}\n")]);
+    assert!(type_errors(&mman, "Main").is_empty());
+    let expected = Ok(Value::String("(a-(b-c))5".to_string()));
+    assert_eq!(run_main(&mman, "Main"), expected);
+    assert_eq!(run_vm(&mman, "Main"), expected);
+
+    let mut lex = Lexer::new("main.ksc", &b"operator infix <+> 2 left -> selector plus:\nreturn 1 <+> 2\n"[..]);
+    let code = Parser::new("main.ksc", lex.process().unwrap()).parse_script().unwrap();
+    match *code[0] {
+        Expression::ReturnStatement(_, ref e) => match **e {
+            Expression::MessageSend { receiver: Some(_), args_or_name: Ok(ref args), line: 2 } => assert_eq!(args[0].0, "plus"),
+            ref e => panic!("Not a send: {:?}", e)
+        },
+        ref e => panic!("Not a return: {:?}", e)
+    }
+}
+
+#[test]
+fn test_user_operator_prefix() {
+    // <+ starts <+>, but is not an operator: a<+b is a < +b
+    let declaration = "operator infix <+> 2 left -> selector plus:\n";
+    let lexed = |source: &str| token_types(Lexer::new("a", (declaration.to_string() + source).as_bytes()).process().unwrap())[9..].to_vec();
+    assert_eq!(lexed("a<+b\n"), vec![TokenType::Identifier, TokenType::LessThan, TokenType::Plus, TokenType::Identifier, TokenType::EndOfFile]);
+    assert_eq!(lexed("a<+"), vec![TokenType::Identifier, TokenType::LessThan, TokenType::Plus, TokenType::EndOfFile]);
+    assert_eq!(lexed("a<+>b\n"), vec![TokenType::Identifier, TokenType::UserOperator(0), TokenType::Identifier, TokenType::EndOfFile]);
+
+    // operator is a word like infix, unless a line starts with operator infix
+    let mut mman = ModuleManager::new();
+    load_script("words.ksc", &b"let operator = 2\nlet infix = operator + 1\nreturn operator * infix\n"[..], &mut mman).unwrap();
+    assert_eq!(run_main(&mman, "words"), Ok(Value::Integer(6)));
+}
+
+#[test]
+fn test_user_operator_redefined() {
+    let mut lex = Lexer::new("Main.kbld", &b"operator infix <+> 2 left -> selector plus:\noperator infix <+> 3 left -> selector times:\n"[..]);
+    assert_eq!(lex.process().unwrap_err().to_string(), "Main.kbld:2: Operator <+> is already defined");
+}
@@ -0,0 +1,137 @@
+// The bytecode format: each message (and the main block) is a function with its own code and constants.
+// Instructions are an opcode byte, followed by their operands. Operands are single bytes (u8), or two
+// bytes in little endian order (u16).
+use std::cell::RefCell;
+use std::collections::HashMap;
+use super::super::compiler::typecheck::Type;
+use super::super::interpreter::Value;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Opcode {
+    Constant, // u16 constant: pushes a value of the constants pool
+    Nothing, // Pushes Nothing
+    Pop,
+    GetLocal, // u8 slot: pushes a local. Slot 0 is the receiver, then the arguments, then the lets
+    SetLocal, // u8 slot: pops into a local
+    GetMember, // u16 constant (name): replaces an instance by one of its members
+    New, // u16 constant (layout): pops the members of a struct, and pushes the new instance
+    Array, // u16 count: pops that many elements, and pushes an array of them
+    Negate,
+    Positive, // Prefix +, which only checks that its operand is a number
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    Greater,
+    Equal,
+    Jump, // u16 offset: jumps forward
+    JumpIfFalse, // u16 offset: pops a Boolean, and jumps forward if it is false
+    Send, // u16 constant (selector), u8 arguments, u16 cache: sends a message to the receiver under the arguments
+    Return, // Returns the top of the stack to the sender
+}
+
+const OPCODES: [Opcode; 21] = [
+    Opcode::Constant, Opcode::Nothing, Opcode::Pop, Opcode::GetLocal, Opcode::SetLocal, Opcode::GetMember,
+    Opcode::New, Opcode::Array, Opcode::Negate, Opcode::Positive, Opcode::Add, Opcode::Subtract, Opcode::Multiply,
+    Opcode::Divide, Opcode::Less, Opcode::Greater, Opcode::Equal, Opcode::Jump, Opcode::JumpIfFalse,
+    Opcode::Send, Opcode::Return,
+];
+
+impl Opcode {
+    pub fn from_byte(b: u8) -> Option<Opcode> {
+        OPCODES.get(b as usize).cloned()
+    }
+
+    // The number of bytes of operands after the opcode
+    pub fn operand_size(&self) -> usize {
+        match *self {
+            Opcode::GetLocal | Opcode::SetLocal => 1,
+            Opcode::Constant | Opcode::GetMember | Opcode::New | Opcode::Array | Opcode::Jump | Opcode::JumpIfFalse => 2,
+            Opcode::Send => 5,
+            _ => 0
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constant {
+    Value(Value), // Literals, and the structs used as receivers of calls
+    Name(String), // Selectors and member names
+    Layout(Type, Vec<String>), // A struct to allocate, with its members in the order they are pushed
+}
+
+// What a send runs: a compiled function, or a native (by their numbers)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    Function(usize),
+    Native(usize),
+}
+
+// What a send site last dispatched to: the type of the receiver, and what ran
+pub type CacheEntry = Option<(Type, Target)>;
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    lines: Vec<(usize, i32)>, // The offsets where the line changes, with the new line
+    // Inline caches of the send sites, in the order of the sends. A cache is only correct for
+    // the selector of its send, so each send has its own.
+    pub caches: Vec<RefCell<CacheEntry>>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, b: u8, line: i32) {
+        if self.lines.last().map(|l| l.1) != Some(line) {
+            self.lines.push((self.code.len(), line));
+        }
+        self.code.push(b);
+    }
+
+    pub fn write_op(&mut self, op: Opcode, line: i32) {
+        self.write(op as u8, line);
+    }
+
+    pub fn write_u16(&mut self, v: u16, line: i32) {
+        self.write(v as u8, line);
+        self.write((v >> 8) as u8, line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        self.code[offset] as u16 | (self.code[offset + 1] as u16) << 8
+    }
+
+    pub fn patch_u16(&mut self, offset: usize, v: u16) {
+        self.code[offset] = v as u8;
+        self.code[offset + 1] = (v >> 8) as u8;
+    }
+
+    // The line of the code at an offset
+    pub fn line_at(&self, offset: usize) -> i32 {
+        match self.lines.binary_search_by_key(&offset, |l| l.0) {
+            Ok(i) => self.lines[i].1,
+            Err(0) => 0,
+            Err(i) => self.lines[i - 1].1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String, // Struct [selector], or main
+    pub file: String,
+    pub arity: usize, // The arguments, after the receiver in slot 0
+    pub slots: usize, // Every local, the receiver and the arguments included
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Default)]
+pub struct Program {
+    pub module: String, // The module compiled for its main block
+    pub file: String,
+    pub script: bool,
+    pub functions: Vec<Function>,
+    pub declarations: HashMap<(String, usize), usize>, // The function of each message declaration, by (module, index)
+    pub main: Option<usize>,
+}
@@ -0,0 +1,321 @@
+// Compiles the syntax tree of every message, and the main block of a module, to bytecode.
+// Names are resolved here, so the VM only deals with slots, constants and selectors.
+use std::cell::RefCell;
+use super::super::compiler::ast::{Expression, selector_name};
+use super::super::compiler::dispatch::Dispatcher;
+use super::super::compiler::module::{Module, ModuleManager};
+use super::super::compiler::token::TokenType;
+use super::super::compiler::typecheck::Type;
+use super::super::interpreter::{RuntimeError, RuntimeErrorKind, Value};
+use super::bytecode::{Chunk, Constant, Function, Opcode, Program};
+
+// The state of the function being compiled
+struct FunctionState<'a> {
+    module: &'a Module,
+    chunk: Chunk,
+    scopes: Vec<Vec<(String, u8)>>, // The slot of every name in scope, innermost scope last
+    slots: usize,
+    line: i32,
+}
+
+impl<'a> FunctionState<'a> {
+    fn error(&self, kind: RuntimeErrorKind) -> Box<RuntimeError> {
+        Box::new(RuntimeError { file: self.module.get_file_name(), line: self.line, kind })
+    }
+
+    fn lookup(&self, name: &str) -> Option<u8> {
+        self.scopes.iter().rev().flat_map(|s| s.iter().rev()).find(|b| b.0 == name).map(|b| b.1)
+    }
+
+    // Every let gets a slot of its own, so a slot never changes what it holds after it is set
+    fn declare(&mut self, name: &str) -> Result<u8, Box<RuntimeError>> {
+        if self.slots > u8::MAX as usize {
+            return Err(self.error(RuntimeErrorKind::Limit("locals".to_string())))
+        }
+        let slot = self.slots as u8;
+        self.slots += 1;
+        self.scopes.last_mut().unwrap().push((name.to_string(), slot));
+        Ok(slot)
+    }
+
+    fn op(&mut self, op: Opcode) {
+        let line = self.line;
+        self.chunk.write_op(op, line);
+    }
+
+    fn byte(&mut self, b: u8) {
+        let line = self.line;
+        self.chunk.write(b, line);
+    }
+
+    fn u16(&mut self, v: u16) {
+        let line = self.line;
+        self.chunk.write_u16(v, line);
+    }
+
+    // The index of a constant, added to the pool if it is not there yet
+    fn constant(&mut self, c: Constant) -> Result<u16, Box<RuntimeError>> {
+        let index = match self.chunk.constants.iter().position(|k| *k == c) {
+            Some(index) => index,
+            None => {
+                self.chunk.constants.push(c);
+                self.chunk.constants.len() - 1
+            }
+        };
+        if index > u16::MAX as usize {
+            return Err(self.error(RuntimeErrorKind::Limit("constants".to_string())))
+        }
+        Ok(index as u16)
+    }
+
+    // Writes a jump with an offset to fill in later, and gives where the offset is
+    fn jump(&mut self, op: Opcode) -> usize {
+        self.op(op);
+        self.u16(0);
+        self.chunk.code.len() - 2
+    }
+
+    // Makes the jump at an offset land after the last instruction
+    fn land(&mut self, at: usize) -> Result<(), Box<RuntimeError>> {
+        let distance = self.chunk.code.len() - (at + 2);
+        if distance > u16::MAX as usize {
+            return Err(self.error(RuntimeErrorKind::Limit("code".to_string())))
+        }
+        self.chunk.patch_u16(at, distance as u16);
+        Ok(())
+    }
+}
+
+pub struct Compiler<'a> {
+    mman: &'a ModuleManager,
+    dispatcher: Dispatcher<'a>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(mman: &'a ModuleManager) -> Compiler<'a> {
+        Compiler { mman, dispatcher: Dispatcher::new(mman) }
+    }
+
+    // Compiles every message of every module, and the main block of m when it has one
+    pub fn compile(&self, m: &Module) -> Result<Program, Box<RuntimeError>> {
+        let mut program = Program { module: m.get_full_name(), file: m.get_file_name(), script: m.is_script(), ..Program::default() };
+        for module in self.mman.find_modules_under("") {
+            for (index, inst) in module.get_code().iter().enumerate() {
+                if let Expression::MessageDeclaration { ref bound_struct, is_call, ref args_or_name, ref selector, ref body, line, .. } = **inst {
+                    let mut params = vec!["this".to_string()];
+                    if let Ok(ref args) = *args_or_name {
+                        params.extend(args.iter().map(|a| a.0.clone()));
+                    }
+                    let name = format!("{}{} [{}]", bound_struct, if is_call { " call" } else { "" }, selector);
+                    let f = self.function(&module, name, &params, &[], body, line)?;
+                    program.declarations.insert((module.get_full_name(), index), program.functions.len());
+                    program.functions.push(f);
+                }
+            }
+        }
+        let main = m.get_code().iter().filter_map(|inst| match **inst {
+            Expression::MainDeclaration { ref body, line } => Some((body, line)),
+            _ => None
+        }).next();
+        if let Some((body, line)) = main {
+            // main runs the lets of its module first, and has no receiver
+            let lets = m.get_code().iter().filter(|inst| matches!(***inst, Expression::LetStatement { .. })).cloned().collect::<Vec<_>>();
+            let f = self.function(m, "main".to_string(), &["".to_string()], &lets, body, line)?;
+            program.main = Some(program.functions.len());
+            program.functions.push(f);
+        }
+        Ok(program)
+    }
+
+    fn function(&self, m: &Module, name: String, params: &[String], prologue: &[Box<Expression>],
+                body: &[Box<Expression>], line: i32) -> Result<Function, Box<RuntimeError>> {
+        let mut state = FunctionState { module: m, chunk: Chunk::default(), scopes: vec![vec![]], slots: 0, line };
+        for p in params.iter() {
+            state.declare(p)?;
+        }
+        for stmt in prologue.iter() {
+            self.statement(&mut state, stmt)?;
+        }
+        self.block(&mut state, body)?;
+        // Falling off the end returns Nothing
+        state.op(Opcode::Nothing);
+        state.op(Opcode::Return);
+        Ok(Function { name, file: m.get_file_name(), arity: params.len() - 1, slots: state.slots, chunk: state.chunk })
+    }
+
+    fn block(&self, state: &mut FunctionState, block: &[Box<Expression>]) -> Result<(), Box<RuntimeError>> {
+        state.scopes.push(vec![]);
+        for stmt in block.iter() {
+            self.statement(state, stmt)?;
+        }
+        state.scopes.pop();
+        Ok(())
+    }
+
+    fn statement(&self, state: &mut FunctionState, stmt: &Expression) -> Result<(), Box<RuntimeError>> {
+        match *stmt {
+            Expression::LetStatement { ref bound_name, ref expression, line, .. } => {
+                state.line = line;
+                self.expression(state, expression)?;
+                // Declared after the expression, which still sees what the name was bound to before
+                let slot = state.declare(bound_name)?;
+                state.op(Opcode::SetLocal);
+                state.byte(slot);
+            },
+            Expression::ReturnStatement(line, ref expression) => {
+                state.line = line;
+                self.expression(state, expression)?;
+                state.op(Opcode::Return);
+            },
+            Expression::IfStatement { ref condition, ref body, ref else_body, line } => {
+                state.line = line;
+                self.expression(state, condition)?;
+                state.line = line;
+                let to_else = state.jump(Opcode::JumpIfFalse);
+                self.block(state, body)?;
+                match *else_body {
+                    Some(ref else_body) => {
+                        let to_end = state.jump(Opcode::Jump);
+                        state.land(to_else)?;
+                        self.block(state, else_body)?;
+                        state.land(to_end)?;
+                    },
+                    None => state.land(to_else)?
+                }
+            },
+            ref expression => {
+                self.expression(state, expression)?;
+                state.op(Opcode::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&self, state: &mut FunctionState, expr: &Expression) -> Result<(), Box<RuntimeError>> {
+        match *expr {
+            Expression::IntegerExpression(ref s) => {
+                let i = s.parse().map_err(|_| state.error(RuntimeErrorKind::InvalidNumber(s.clone())))?;
+                self.push_value(state, Value::Integer(i))?;
+            },
+            Expression::FloatExpression(ref s) => {
+                let f = s.parse().map_err(|_| state.error(RuntimeErrorKind::InvalidNumber(s.clone())))?;
+                self.push_value(state, Value::Float(f))?;
+            },
+            Expression::StringExpression(ref s) => self.push_value(state, Value::String(s.clone()))?,
+            Expression::BooleanExpression(b) => self.push_value(state, Value::Boolean(b))?,
+            Expression::VariableExpression(line, ref name) => {
+                state.line = line;
+                let slot = state.lookup(name).ok_or_else(|| state.error(RuntimeErrorKind::UnknownVariable(name.clone())))?;
+                state.op(Opcode::GetLocal);
+                state.byte(slot);
+            },
+            Expression::StructExpression(line, ref name) => {
+                state.line = line;
+                let t = self.named_type(state, name, line)?;
+                self.push_value(state, Value::Class(t))?;
+            },
+            Expression::PrefixExpression(line, op, ref e) => {
+                self.expression(state, e)?;
+                state.line = line;
+                // The parser only makes prefix - and +
+                state.op(if op == TokenType::Minus { Opcode::Negate } else { Opcode::Positive });
+            },
+            Expression::BinaryExpression(line, op, ref l, ref r) => {
+                self.expression(state, l)?;
+                self.expression(state, r)?;
+                state.line = line;
+                state.op(match op {
+                    TokenType::Plus => Opcode::Add,
+                    TokenType::Minus => Opcode::Subtract,
+                    TokenType::Asterisk => Opcode::Multiply,
+                    TokenType::Backslash => Opcode::Divide,
+                    TokenType::LessThan => Opcode::Less,
+                    TokenType::GreaterThan => Opcode::Greater,
+                    _ => Opcode::Equal, // The parser only makes the operators above, and ==
+                });
+            },
+            Expression::MemberExpression(line, ref e, ref name) => {
+                self.expression(state, e)?;
+                state.line = line;
+                let c = state.constant(Constant::Name(name.clone()))?;
+                state.op(Opcode::GetMember);
+                state.u16(c);
+            },
+            Expression::InstanceExpression { ref struct_name, ref members, line } => {
+                state.line = line;
+                let t = self.named_type(state, struct_name, line)?;
+                let declared = self.dispatcher.members(&t).ok_or_else(|| state.error(RuntimeErrorKind::NotInstantiable(t.clone())))?;
+                if let Some(m) = members.iter().find(|m| !declared.iter().any(|d| d.0 == m.0)) {
+                    return Err(state.error(RuntimeErrorKind::UnknownMember(t.clone(), m.0.clone())))
+                }
+                if let Some(d) = declared.iter().find(|d| !members.iter().any(|m| m.0 == d.0)) {
+                    return Err(state.error(RuntimeErrorKind::MissingMember(t.clone(), d.0.clone())))
+                }
+                for m in members.iter() {
+                    self.expression(state, &m.1)?;
+                }
+                state.line = line;
+                let c = state.constant(Constant::Layout(t, members.iter().map(|m| m.0.clone()).collect()))?;
+                state.op(Opcode::New);
+                state.u16(c);
+            },
+            Expression::ArrayExpression(line, ref elements) => {
+                for e in elements.iter() {
+                    self.expression(state, e)?;
+                }
+                state.line = line;
+                if elements.len() > u16::MAX as usize {
+                    return Err(state.error(RuntimeErrorKind::Limit("array elements".to_string())))
+                }
+                state.op(Opcode::Array);
+                state.u16(elements.len() as u16);
+            },
+            Expression::MessageSend { ref receiver, ref args_or_name, line } => {
+                state.line = line;
+                let sel = selector_name(args_or_name);
+                match *receiver {
+                    Some(ref e) => self.expression(state, e)?,
+                    None => match (state.lookup("this"), self.dispatcher.implicit_receiver(&sel)) {
+                        (Some(slot), _) => {
+                            state.op(Opcode::GetLocal);
+                            state.byte(slot);
+                        },
+                        (None, Some(Type::Class(t))) => self.push_value(state, Value::Class(*t))?,
+                        _ => return Err(state.error(RuntimeErrorKind::NoReceiver(sel.clone())))
+                    }
+                }
+                let mut argc = 0;
+                if let Ok(ref args) = *args_or_name {
+                    for a in args.iter() {
+                        self.expression(state, &a.1)?;
+                    }
+                    argc = args.len();
+                }
+                state.line = line;
+                if argc > u8::MAX as usize || state.chunk.caches.len() > u16::MAX as usize {
+                    return Err(state.error(RuntimeErrorKind::Limit(if argc > u8::MAX as usize { "arguments" } else { "sends" }.to_string())))
+                }
+                let c = state.constant(Constant::Name(sel))?;
+                let cache = state.chunk.caches.len() as u16;
+                state.chunk.caches.push(RefCell::new(None));
+                state.op(Opcode::Send);
+                state.u16(c);
+                state.byte(argc as u8);
+                state.u16(cache);
+            },
+            _ => state.op(Opcode::Nothing)
+        }
+        Ok(())
+    }
+
+    fn push_value(&self, state: &mut FunctionState, v: Value) -> Result<(), Box<RuntimeError>> {
+        let c = state.constant(Constant::Value(v))?;
+        state.op(Opcode::Constant);
+        state.u16(c);
+        Ok(())
+    }
+
+    fn named_type(&self, state: &FunctionState, name: &str, line: i32) -> Result<Type, Box<RuntimeError>> {
+        self.dispatcher.named_type(state.module, name, line).map_err(|kind| state.error(RuntimeErrorKind::Unresolved(kind)))
+    }
+}
@@ -0,0 +1,65 @@
+// Prints compiled functions as text, one instruction per line:
+//     offset line opcode operands (what the constant or the cache holds)
+use super::bytecode::{Constant, Function, Opcode, Program, Target};
+
+pub fn disassemble_program(program: &Program) -> String {
+    program.functions.iter().map(disassemble).collect::<Vec<_>>().join("\n")
+}
+
+pub fn disassemble(f: &Function) -> String {
+    let chunk = &f.chunk;
+    let mut s = format!("== {} ({} argument(s), {} slot(s)) ==\n", f.name, f.arity, f.slots);
+    let mut offset = 0;
+    let mut last_line = None;
+    while offset < chunk.code.len() {
+        let line = chunk.line_at(offset);
+        let shown = match last_line {
+            Some(l) if l == line => "   |".to_string(),
+            _ => format!("{:4}", line),
+        };
+        last_line = Some(line);
+        let op = match Opcode::from_byte(chunk.code[offset]) {
+            Some(op) => op,
+            None => {
+                s = s + &format!("{:04} {} <invalid {}>\n", offset, shown, chunk.code[offset]);
+                offset += 1;
+                continue
+            }
+        };
+        let at = offset + 1;
+        let operands = match op {
+            Opcode::GetLocal | Opcode::SetLocal => format!(" {}", chunk.code[at]),
+            Opcode::Constant | Opcode::GetMember | Opcode::New => {
+                let c = chunk.read_u16(at);
+                format!(" {} ({})", c, show_constant(&chunk.constants[c as usize]))
+            },
+            Opcode::Array => format!(" {}", chunk.read_u16(at)),
+            Opcode::Jump | Opcode::JumpIfFalse => {
+                let distance = chunk.read_u16(at) as usize;
+                format!(" {} (to {:04})", distance, at + 2 + distance)
+            },
+            Opcode::Send => {
+                let c = chunk.read_u16(at);
+                let cache = chunk.read_u16(at + 3);
+                let cached = match *chunk.caches[cache as usize].borrow() {
+                    Some((ref t, Target::Function(f))) => format!("{} -> function {}", t, f),
+                    Some((ref t, Target::Native(n))) => format!("{} -> native {}", t, n),
+                    None => "empty".to_string(),
+                };
+                format!(" {} ({}) {} cache {} ({})", c, show_constant(&chunk.constants[c as usize]), chunk.code[at + 2], cache, cached)
+            },
+            _ => String::new()
+        };
+        s = s + &format!("{:04} {} {:?}{}\n", offset, shown, op, operands);
+        offset = at + op.operand_size();
+    }
+    s
+}
+
+fn show_constant(c: &Constant) -> String {
+    match *c {
+        Constant::Value(ref v) => format!("{:?}", v),
+        Constant::Name(ref name) => name.clone(),
+        Constant::Layout(ref t, ref members) => format!("{} {{{}}}", t, members.join(", ")),
+    }
+}
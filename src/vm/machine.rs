@@ -0,0 +1,266 @@
+// The stack machine that runs compiled programs
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use super::super::compiler::dispatch::{Dispatcher, DispatchError};
+use super::super::compiler::module::ModuleManager;
+use super::super::compiler::token::TokenType;
+use super::super::compiler::typecheck::Type;
+use super::super::interpreter::{Context, Engine, Instance, Io, MAX_DEPTH, Natives, RuntimeError, RuntimeErrorKind, Value, binary, unary};
+use super::bytecode::{Constant, Opcode, Program, Target};
+
+// A function being run: where it is in its code, and where its slots start on the stack
+struct CallFrame {
+    function: usize,
+    ip: usize,
+    base: usize,
+}
+
+pub struct Vm<'a> {
+    program: &'a Program,
+    dispatcher: Dispatcher<'a>, // For sends that miss their inline cache
+    natives: Natives,
+    context: Context,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(mman: &'a ModuleManager, program: &'a Program) -> Vm<'a> {
+        Vm { program, dispatcher: Dispatcher::new(mman), natives: Natives::new(), context: Context::default(), stack: vec![], frames: vec![] }
+    }
+
+    // Runs with another Io than the standard input and output
+    pub fn with_io(mut self, io: Box<dyn Io>) -> Vm<'a> {
+        self.context.io = io;
+        self
+    }
+
+    // The arguments that Std.System gives to the program
+    pub fn with_args(mut self, args: Vec<String>) -> Vm<'a> {
+        self.context.launch.args = args;
+        self
+    }
+
+    // Runs the main block of the program. Gives what main returns, or Nothing.
+    pub fn run(&mut self) -> Result<Value, Box<RuntimeError>> {
+        let main = match self.program.main {
+            Some(main) => main,
+            None => return Err(Box::new(RuntimeError {
+                file: self.program.file.clone(), line: 0, kind: RuntimeErrorKind::NoMain(self.program.module.clone())
+            }))
+        };
+        self.context.launch.script = self.program.script;
+        self.stack.clear();
+        self.frames.clear();
+        self.stack.push(Value::Nothing); // main has no receiver
+        let result = self.call(main, 0).map_err(|kind| self.error(kind)).and_then(|_| self.execute(0));
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+        }
+        result
+    }
+
+    // Starts a function, with its receiver and arguments already on the stack. main is the first frame,
+    // so the messages can be as deep as in the interpreter.
+    fn call(&mut self, function: usize, argc: usize) -> Result<(), RuntimeErrorKind> {
+        if self.frames.len() > MAX_DEPTH {
+            return Err(RuntimeErrorKind::StackOverflow)
+        }
+        let base = self.stack.len() - argc - 1;
+        let slots = self.program.functions[function].slots;
+        self.stack.resize(base + slots.max(argc + 1), Value::Nothing);
+        self.frames.push(CallFrame { function, ip: 0, base });
+        Ok(())
+    }
+
+    // The errors of messages sent by natives keep where they happened
+    fn error(&self, kind: RuntimeErrorKind) -> Box<RuntimeError> {
+        let kind = match kind {
+            RuntimeErrorKind::Failed(e) => return e,
+            kind => kind
+        };
+        let frame = self.frames.last().unwrap();
+        let f = &self.program.functions[frame.function];
+        // The ip is past the instruction that failed, which is on the line of its last byte
+        Box::new(RuntimeError { file: f.file.clone(), line: f.chunk.line_at(frame.ip.saturating_sub(1)), kind })
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    // What a send runs, for a receiver of type t
+    fn target(&self, t: &Type, sel: &str) -> Result<Target, RuntimeErrorKind> {
+        let d = match self.dispatcher.resolve(t, sel) {
+            Ok(d) => d,
+            Err(DispatchError::DoesNotUnderstand(t, sel)) => return Err(RuntimeErrorKind::DoesNotUnderstand(t, sel))
+        };
+        let is_call = matches!(*t, Type::Class(_));
+        match self.natives.find(&d.owner, is_call, sel) {
+            Some(n) => Ok(Target::Native(n)),
+            None => match self.program.declarations.get(&(d.module, d.index)) {
+                Some(&f) => Ok(Target::Function(f)),
+                None => Err(RuntimeErrorKind::DoesNotUnderstand(t.clone(), sel.to_string()))
+            }
+        }
+    }
+
+    // Runs until the frames drop back to depth, which is 0 for main and deeper for the messages
+    // that natives send. Gives what the last frame returned.
+    fn execute(&mut self, depth: usize) -> Result<Value, Box<RuntimeError>> {
+        let program = self.program;
+        loop {
+            let (function, base) = {
+                let frame = self.frames.last().unwrap();
+                (frame.function, frame.base)
+            };
+            let chunk = &program.functions[function].chunk;
+            let ip = self.frames.last().unwrap().ip;
+            let op = match Opcode::from_byte(chunk.code[ip]) {
+                Some(op) => op,
+                None => panic!("Invalid opcode {} in {}", chunk.code[ip], program.functions[function].name)
+            };
+            let operands = ip + 1;
+            self.frames.last_mut().unwrap().ip = operands + op.operand_size();
+            match op {
+                Opcode::Constant => match chunk.constants[chunk.read_u16(operands) as usize] {
+                    Constant::Value(ref v) => self.stack.push(v.clone()),
+                    ref c => panic!("Constant {:?} is not a value", c)
+                },
+                Opcode::Nothing => self.stack.push(Value::Nothing),
+                Opcode::Pop => { self.pop(); },
+                Opcode::GetLocal => {
+                    let v = self.stack[base + chunk.code[operands] as usize].clone();
+                    self.stack.push(v);
+                },
+                Opcode::SetLocal => {
+                    let v = self.pop();
+                    self.stack[base + chunk.code[operands] as usize] = v;
+                },
+                Opcode::GetMember => {
+                    let name = match chunk.constants[chunk.read_u16(operands) as usize] {
+                        Constant::Name(ref name) => name,
+                        ref c => panic!("Constant {:?} is not a name", c)
+                    };
+                    let v = self.pop();
+                    let member = match v {
+                        Value::Instance(ref i) => i.members.get(name).cloned(),
+                        _ => None
+                    };
+                    match member {
+                        Some(m) => self.stack.push(m),
+                        None => return Err(self.error(RuntimeErrorKind::UnknownMember(v.get_type(), name.clone())))
+                    }
+                },
+                Opcode::New => {
+                    let (t, names) = match chunk.constants[chunk.read_u16(operands) as usize] {
+                        Constant::Layout(ref t, ref names) => (t, names),
+                        ref c => panic!("Constant {:?} is not a layout", c)
+                    };
+                    let values = self.stack.split_off(self.stack.len() - names.len());
+                    let members = names.iter().cloned().zip(values).collect::<BTreeMap<_, _>>();
+                    self.stack.push(Value::Instance(Rc::new(Instance { struct_type: t.clone(), members })));
+                },
+                Opcode::Array => {
+                    let elements = self.stack.split_off(self.stack.len() - chunk.read_u16(operands) as usize);
+                    self.stack.push(Value::Array(Rc::new(elements)));
+                },
+                Opcode::Negate | Opcode::Positive => {
+                    let v = self.pop();
+                    let op = if op == Opcode::Negate { TokenType::Minus } else { TokenType::Plus };
+                    match unary(op, v) {
+                        Ok(v) => self.stack.push(v),
+                        Err(kind) => return Err(self.error(kind))
+                    }
+                },
+                Opcode::Add | Opcode::Subtract | Opcode::Multiply | Opcode::Divide | Opcode::Less | Opcode::Greater | Opcode::Equal => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let op = match op {
+                        Opcode::Add => TokenType::Plus,
+                        Opcode::Subtract => TokenType::Minus,
+                        Opcode::Multiply => TokenType::Asterisk,
+                        Opcode::Divide => TokenType::Backslash,
+                        Opcode::Less => TokenType::LessThan,
+                        Opcode::Greater => TokenType::GreaterThan,
+                        _ => TokenType::DoubleEqual,
+                    };
+                    match binary(op, l, r) {
+                        Ok(v) => self.stack.push(v),
+                        Err(kind) => return Err(self.error(kind))
+                    }
+                },
+                Opcode::Jump => self.frames.last_mut().unwrap().ip += chunk.read_u16(operands) as usize,
+                Opcode::JumpIfFalse => match self.pop() {
+                    Value::Boolean(true) => {},
+                    Value::Boolean(false) => self.frames.last_mut().unwrap().ip += chunk.read_u16(operands) as usize,
+                    v => return Err(self.error(RuntimeErrorKind::NotBoolean(v.get_type())))
+                },
+                Opcode::Send => {
+                    let argc = chunk.code[operands + 2] as usize;
+                    let receiver = self.stack[self.stack.len() - argc - 1].get_type();
+                    let cache = &chunk.caches[chunk.read_u16(operands + 3) as usize];
+                    let cached = match *cache.borrow() {
+                        Some((ref t, target)) if *t == receiver => Some(target),
+                        _ => None
+                    };
+                    let target = match cached {
+                        Some(target) => target,
+                        None => {
+                            let sel = match chunk.constants[chunk.read_u16(operands) as usize] {
+                                Constant::Name(ref sel) => sel,
+                                ref c => panic!("Constant {:?} is not a selector", c)
+                            };
+                            let target = self.target(&receiver, sel).map_err(|kind| self.error(kind))?;
+                            *cache.borrow_mut() = Some((receiver, target));
+                            target
+                        }
+                    };
+                    match target {
+                        Target::Function(f) => self.call(f, argc).map_err(|kind| self.error(kind))?,
+                        Target::Native(n) => {
+                            let args = self.stack.split_off(self.stack.len() - argc);
+                            let receiver = self.pop();
+                            match self.natives.get(n).call(self, &receiver, &args) {
+                                Ok(v) => self.stack.push(v),
+                                Err(kind) => return Err(self.error(kind))
+                            }
+                        }
+                    }
+                },
+                Opcode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(result)
+                    }
+                    self.stack.push(result);
+                },
+            }
+        }
+    }
+}
+
+impl<'a> Engine for Vm<'a> {
+    fn context(&mut self) -> &mut Context {
+        &mut self.context
+    }
+
+    fn send(&mut self, receiver: Value, selector: &str, args: Vec<Value>) -> Result<Value, RuntimeErrorKind> {
+        match self.target(&receiver.get_type(), selector)? {
+            Target::Native(n) => self.natives.get(n).call(self, &receiver, &args),
+            Target::Function(f) => {
+                let (depth, argc) = (self.frames.len(), args.len());
+                self.stack.push(receiver);
+                self.stack.extend(args);
+                if let Err(kind) = self.call(f, argc) {
+                    self.stack.truncate(self.stack.len() - argc - 1);
+                    return Err(kind)
+                }
+                self.execute(depth).map_err(RuntimeErrorKind::Failed)
+            }
+        }
+    }
+}
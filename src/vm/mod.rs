@@ -0,0 +1,10 @@
+// Runs Kobold code faster than the interpreter: message bodies are compiled to bytecode,
+// and run on a stack machine with inline caches at every send
+pub mod bytecode;
+pub mod compiler;
+pub mod machine;
+pub mod disasm;
+
+pub use self::compiler::Compiler;
+pub use self::machine::Vm;
+pub use self::disasm::disassemble_program;